@@ -0,0 +1,228 @@
+use device::program::DEFAULT_REGS;
+use device::Opcode;
+use device::Program;
+use device::Reg;
+use std::collections::HashMap;
+use Result;
+
+///Assembler for device programs.
+///
+///```text
+///; comments start with a semicolon
+///.regs 6              ; register count, default 6
+///.reg n 4             ; names register 4 `n`, `r0`..`r5` always work
+///#ip 3                ; binds the ip register, also takes a name
+///        seti 1 _ i   ; `_` is 0, unused operands may be left out
+///loop:   addi i 1 i
+///        gtrr i n t
+///        jmp +t       ; `addr t ip ip`, skips the next instruction if t is 1
+///        jmp loop     ; `seti loop-1 0 ip`
+///```
+///
+///Labels can also be used as immediate operands and evaluate to their address.
+pub fn assemble(src: &str) -> Result<Program> {
+    let mut asm = Assembler::new();
+    for (ix, line) in src.lines().enumerate() {
+        asm.line(ix + 1, line)
+            .map_err(|e| format!("line {}: {}", ix + 1, e))?;
+    }
+
+    asm.finish()
+}
+
+///Encodes a program in the numeric day 16 format with the reference
+///opcode numbering. The format has no ip binding.
+pub fn to_numeric(p: &Program) -> Result<String> {
+    let reference: Vec<usize> = (0..Opcode::count()).collect();
+    p.to_numeric(&reference)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Reg,
+    Imm,
+    Unused
+}
+
+struct Line {
+    no: usize,
+    mnemonic: String,
+    args: Vec<String>
+}
+
+struct Assembler {
+    regs: usize,
+    names: HashMap<String, usize>,
+    labels: HashMap<String, usize>,
+    ip_reg: Option<Reg>,
+    lines: Vec<Line>
+}
+
+///Operand kinds of a and b, c is always a register
+fn kinds(mnemonic: &str) -> Result<(Kind, Kind)> {
+    let k = |c: u8| if c == b'r' { Kind::Reg } else { Kind::Imm };
+    let m = mnemonic.as_bytes();
+    if m.len() != 4 {
+        return Err(format!("Invalid opcode {}", mnemonic).into());
+    }
+
+    let kinds = match &mnemonic[..2] {
+        "ad" | "mu" | "ba" | "bo" => (Kind::Reg, k(m[3])),
+        "se" => (k(m[3]), Kind::Unused),
+        "gt" | "eq" => (k(m[2]), k(m[3])),
+        _ => return Err(format!("Invalid opcode {}", mnemonic).into())
+    };
+
+    Ok(kinds)
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return false
+    }
+
+    chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            regs: DEFAULT_REGS,
+            names: HashMap::new(),
+            labels: HashMap::new(),
+            ip_reg: None,
+            lines: Vec::new()
+        }
+    }
+
+    fn line(&mut self, no: usize, line: &str) -> Result<()> {
+        let mut line = match line.find(';') {
+            Some(ix) => &line[..ix],
+            None => line
+        }.trim();
+
+        if let Some(ix) = line.find(':') {
+            let label = line[..ix].trim();
+            if !is_ident(label) {
+                return Err(format!("Invalid label {}", label).into());
+            }
+            if self.labels.insert(label.to_string(), self.lines.len()).is_some() {
+                return Err(format!("Duplicate label {}", label).into());
+            }
+            line = line[ix + 1..].trim();
+        }
+
+        let mut parts = line.split_whitespace();
+        let first = match parts.next() {
+            Some(first) => first,
+            None => return Ok(())
+        };
+        let args: Vec<String> = parts.map(|s| s.to_string()).collect();
+
+        match first {
+            ".regs" => {
+                if !self.lines.is_empty() || !self.names.is_empty() || self.ip_reg.is_some() {
+                    return Err(".regs must come first".into());
+                }
+                self.regs = args.first().ok_or("Missing register count")?.parse()?;
+            }
+            ".reg" => {
+                if args.len() != 2 || !is_ident(&args[0]) {
+                    return Err("Expected .reg <name> <index>".into());
+                }
+                let ix = Reg::new(args[1].parse()?, self.regs)?.index();
+                self.names.insert(args[0].clone(), ix);
+            }
+            "#ip" => {
+                let arg = args.first().ok_or("Missing ip register")?;
+                self.ip_reg = Some(self.reg(arg)?);
+            }
+            _ => self.lines.push(Line { no, mnemonic: first.to_string(), args })
+        }
+
+        Ok(())
+    }
+
+    fn reg(&self, s: &str) -> Result<Reg> {
+        let ix = match self.names.get(s) {
+            Some(&ix) => ix,
+            None if s.starts_with('r') => s[1..].parse()
+                .map_err(|_| format!("Unknown register {}", s))?,
+            None => s.parse().map_err(|_| format!("Unknown register {}", s))?
+        };
+
+        Reg::new(ix, self.regs)
+    }
+
+    fn imm(&self, s: &str) -> Result<u64> {
+        if let Some(&addr) = self.labels.get(s) {
+            return Ok(addr as u64);
+        }
+
+        s.parse()
+            .map_err(|_| format!("Unknown label {}", s).into())
+    }
+
+    fn operand(&self, s: Option<&String>, kind: Kind) -> Result<u64> {
+        let s = match (s.map(|s| s.as_str()), kind) {
+            (None, Kind::Unused) | (Some("_"), _) => return Ok(0),
+            (None, _) => return Err("Missing operand".into()),
+            (Some(s), _) => s
+        };
+
+        match kind {
+            Kind::Reg => Ok(self.reg(s)?.index() as u64),
+            Kind::Imm | Kind::Unused => self.imm(s)
+        }
+    }
+
+    fn jump(&self, args: &[String]) -> Result<Opcode> {
+        let ip = self.ip_reg.ok_or("jmp needs an #ip register")?;
+        let target = match args {
+            [target] => target,
+            _ => return Err("Expected jmp <label> or jmp +<reg>".into())
+        };
+
+        if let Some(reg) = target.strip_prefix('+') {
+            return Ok(Opcode::Addr { a: self.reg(reg)?, b: ip, c: ip });
+        }
+
+        let addr = self.imm(target)?;
+        if addr == 0 {
+            return Err("Cannot jump to address 0, the ip is incremented after the jump".into());
+        }
+
+        Ok(Opcode::Seti { a: addr - 1, b: 0, c: ip })
+    }
+
+    fn instruction(&self, l: &Line) -> Result<Opcode> {
+        if l.mnemonic == "jmp" {
+            return self.jump(&l.args);
+        }
+
+        let (ka, kb) = kinds(&l.mnemonic)?;
+        let args = &l.args;
+
+        //Unused b may be left out
+        let (a, b, c) = match (args.len(), kb) {
+            (2, Kind::Unused) => (args.first(), None, args.get(1)),
+            (3, _) => (args.first(), args.get(1), args.get(2)),
+            _ => return Err(format!("Wrong number of operands for {}", l.mnemonic).into())
+        };
+
+        let a = self.operand(a, ka)?;
+        let b = self.operand(b, kb)?;
+        let c = self.operand(c, Kind::Reg)?;
+        Opcode::from_mnemonic(&l.mnemonic, a, b, c, self.regs)
+    }
+
+    fn finish(self) -> Result<Program> {
+        let ops = self.lines.iter()
+            .map(|l| self.instruction(l).map_err(|e| format!("line {}: {}", l.no, e).into()))
+            .collect::<Result<Vec<Opcode>>>()?;
+
+        Ok(Program::new(ops, self.ip_reg, self.regs))
+    }
+}
@@ -0,0 +1,243 @@
+use device::flow::blocks;
+use device::flow::BinOp;
+use device::flow::Block;
+use device::flow::Exit;
+use device::flow::Expr;
+use device::flow::Operand;
+use device::Program;
+use petgraph::algo::dominators::simple_fast;
+use petgraph::algo::dominators::Dominators;
+use petgraph::graph::DiGraph;
+use petgraph::graph::NodeIndex;
+use petgraph::Direction;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Node {
+    Block(Block),
+    ///Every jump outside the program ends up here
+    Halt
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Edge {
+    FallThrough,
+    Jump,
+    ///Taken `addr x ip ip` after a comparison
+    Skip,
+    ///Possible target of a jump to a run time value
+    Computed
+}
+
+///Natural loop, `body` includes the header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: NodeIndex,
+    pub latches: Vec<NodeIndex>,
+    pub body: BTreeSet<NodeIndex>
+}
+
+pub struct Cfg {
+    pub graph: DiGraph<Node, Edge>,
+    pub entry: NodeIndex,
+    pub halt: NodeIndex,
+    starts: HashMap<usize, NodeIndex>
+}
+
+///Values of `x + c` and `x | c` are at least `c`, so the jump continues
+///at `c + 1` or later
+fn lowest_target(e: &Expr) -> usize {
+    match *e {
+        Expr::Bin(BinOp::Add, _, Operand::Imm(c)) | Expr::Bin(BinOp::Add, Operand::Imm(c), _) |
+        Expr::Bin(BinOp::Or, _, Operand::Imm(c)) | Expr::Bin(BinOp::Or, Operand::Imm(c), _) => {
+            (c as usize).saturating_add(1)
+        }
+        _ => 0
+    }
+}
+
+impl Cfg {
+    ///Builds the graph. Computed jumps get an edge to every block they
+    ///might reach and to halt.
+    pub fn new(p: &Program) -> Cfg {
+        let mut graph = DiGraph::new();
+        let blocks = blocks(p);
+
+        let starts: HashMap<usize, NodeIndex> = blocks.iter()
+            .map(|b| (b.start, graph.add_node(Node::Block(*b))))
+            .collect();
+        let halt = graph.add_node(Node::Halt);
+        let node = |addr: usize| starts.get(&addr).cloned().unwrap_or(halt);
+
+        for b in blocks.iter() {
+            let from = node(b.start);
+            let edges = match b.exit {
+                Exit::Next => vec![(node(b.end), Edge::FallThrough)],
+                Exit::Jump(t) => vec![(node(t), Edge::Jump)],
+                Exit::Skip(_) => vec![
+                    (node(b.end), Edge::FallThrough),
+                    (node(b.end + 1), Edge::Skip)
+                ],
+                Exit::Computed(e) => {
+                    let min = lowest_target(&e);
+                    graph.node_indices()
+                        .filter(|&n| match graph[n] {
+                            Node::Block(b) => b.start >= min,
+                            Node::Halt => true
+                        })
+                        .map(|n| (n, Edge::Computed))
+                        .collect()
+                }
+            };
+
+            for (to, e) in edges {
+                graph.update_edge(from, to, e);
+            }
+        }
+
+        Cfg {
+            entry: node(0),
+            graph,
+            halt,
+            starts
+        }
+    }
+
+    ///Node of the block starting at `addr`
+    pub fn node_at(&self, addr: usize) -> Option<NodeIndex> {
+        self.starts.get(&addr).cloned()
+    }
+
+    pub fn dominators(&self) -> Dominators<NodeIndex> {
+        simple_fast(&self.graph, self.entry)
+    }
+
+    ///Blocks that can not be reached from the entry
+    pub fn unreachable(&self) -> Vec<NodeIndex> {
+        let doms = self.dominators();
+        self.graph.node_indices()
+            .filter(|&n| doms.dominators(n).is_none())
+            .collect()
+    }
+
+    ///Natural loops of the back edges, loops sharing a header are merged
+    pub fn loops(&self) -> Vec<Loop> {
+        let doms = self.dominators();
+        let mut loops: Vec<Loop> = Vec::new();
+
+        for e in self.graph.edge_indices() {
+            let (from, header) = self.graph.edge_endpoints(e).unwrap();
+            let back = doms.dominators(from)
+                .is_some_and(|mut ds| ds.any(|d| d == header));
+            if !back {
+                continue;
+            }
+
+            let mut body = BTreeSet::new();
+            body.insert(header);
+            let mut stack = vec![from];
+            while let Some(n) = stack.pop() {
+                if body.insert(n) {
+                    stack.extend(self.graph.neighbors_directed(n, Direction::Incoming));
+                }
+            }
+
+            match loops.iter_mut().find(|l| l.header == header) {
+                Some(l) => {
+                    l.latches.push(from);
+                    l.body.extend(body);
+                }
+                None => loops.push(Loop { header, latches: vec![from], body })
+            }
+        }
+
+        loops.sort_by_key(|l| self.address(l.header));
+        loops
+    }
+
+    ///Start address of a node, halt sorts last
+    fn address(&self, n: NodeIndex) -> usize {
+        match self.graph[n] {
+            Node::Block(b) => b.start,
+            Node::Halt => usize::MAX
+        }
+    }
+
+    ///Graphviz DOT with the instructions of every block
+    pub fn to_dot(&self, p: &Program) -> String {
+        let mut s = String::new();
+        writeln!(s, "digraph cfg {{").unwrap();
+        writeln!(s, "    node [shape=box, fontname=monospace];").unwrap();
+
+        for n in self.graph.node_indices() {
+            let label = match self.graph[n] {
+                Node::Block(b) => (b.start..b.end)
+                    .map(|i| format!("{:3}: {}\\l", i, p.ops[i]))
+                    .collect::<String>(),
+                Node::Halt => "halt".to_string()
+            };
+            writeln!(s, "    n{} [label=\"{}\"];", n.index(), label).unwrap();
+        }
+
+        for e in self.graph.edge_indices() {
+            let (from, to) = self.graph.edge_endpoints(e).unwrap();
+            let style = match self.graph[e] {
+                Edge::FallThrough => "",
+                Edge::Jump => " [color=blue]",
+                Edge::Skip => " [color=darkgreen, label=\"skip\"]",
+                Edge::Computed => " [style=dashed, color=gray]"
+            };
+            writeln!(s, "    n{} -> n{}{};", from.index(), to.index(), style).unwrap();
+        }
+
+        writeln!(s, "}}").unwrap();
+        s
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match *self {
+            Node::Block(b) => write!(f, "{}..{}", b.start, b.end),
+            Node::Halt => write!(f, "halt")
+        }
+    }
+}
+
+///Lists blocks with their immediate dominators, then the loops
+impl Display for Cfg {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let doms = self.dominators();
+
+        for n in self.graph.node_indices() {
+            let succ: Vec<String> = self.graph.neighbors(n)
+                .map(|m| self.graph[m].to_string())
+                .collect();
+
+            write!(f, "{:>10} -> [{}]", self.graph[n].to_string(), succ.join(", "))?;
+            match doms.immediate_dominator(n) {
+                Some(d) => writeln!(f, ", idom {}", self.graph[d])?,
+                None if n == self.entry => writeln!(f, ", entry")?,
+                None => writeln!(f, ", unreachable")?
+            }
+        }
+
+        for l in self.loops() {
+            let body: Vec<String> = l.body.iter()
+                .map(|&n| self.graph[n].to_string())
+                .collect();
+            let latches: Vec<String> = l.latches.iter()
+                .map(|&n| self.graph[n].to_string())
+                .collect();
+
+            writeln!(f, "loop {}: latches [{}], body [{}]",
+                     self.graph[l.header], latches.join(", "), body.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,356 @@
+use device::cfg::Cfg;
+use device::decompile::decompile;
+use device::fault::Fault;
+use device::history::History;
+use device::history::Snapshot;
+use device::Cpu;
+use device::Program;
+use device::Reg;
+use std::collections::BTreeSet;
+use std::io::BufRead;
+use std::io::Write;
+use Result;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Watch {
+    Change(Reg),
+    Value(Reg, u64)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(usize),
+    Watch { ip: usize, reg: Reg, old: u64, new: u64 },
+    Halted,
+    Fault { ip: usize, fault: Fault },
+    Stepped
+}
+
+pub struct Debugger<'a> {
+    program: &'a Program,
+    history: History<'a>,
+    breakpoints: BTreeSet<usize>,
+    watches: Vec<Watch>
+}
+
+///Steps `find` searches ahead by default
+const FIND_LIMIT: u64 = 10_000_000;
+
+const HELP: &str = "\
+s, step [n]          execute n instructions (default 1)
+c, continue          run until a breakpoint, watchpoint or halt
+b, break <ix>        add breakpoint at instruction ix
+d, delete <ix>       remove breakpoint
+w, watch <r> [val]   stop when register r changes or becomes val
+u, unwatch <r>       remove watchpoints of register r
+set <r> <val>        set register r
+ip <ix>              set the instruction pointer
+back [n]             undo n instructions (default 1)
+save <file>          write registers, ip and step count to a file
+load <file>          continue from a saved snapshot
+f, find <r> <v> [n]  go to the first step at which register r is v, searching
+                     from the oldest undoable step to n steps ahead
+export <file>        write the program as mnemonic text
+decompile [file]     print the program as structured pseudo code, or write it
+cfg [file]           print blocks with their dominators and the loops, or
+                     write the control flow graph as Graphviz DOT
+r, regs              print registers
+l, list [n]          disassemble n instructions around the ip
+i, info              list breakpoints and watchpoints
+q, quit              exit the debugger";
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a Program, cpu: Cpu) -> Debugger<'a> {
+        Debugger {
+            program,
+            history: History::new(program, cpu),
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new()
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        self.history.cpu()
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.history.steps()
+    }
+
+    pub fn history(&mut self) -> &mut History<'a> {
+        &mut self.history
+    }
+
+    pub fn add_breakpoint(&mut self, ix: usize) {
+        self.breakpoints.insert(ix);
+    }
+
+    pub fn add_watch(&mut self, w: Watch) {
+        self.watches.push(w);
+    }
+
+    ///Executes a single instruction and checks the watchpoints
+    pub fn step(&mut self) -> Stop {
+        let ip = self.cpu().ip();
+        let before = self.cpu().clone();
+
+        match self.history.step() {
+            Ok(true) => {}
+            Ok(false) => return Stop::Halted,
+            Err(fault) => return Stop::Fault { ip, fault }
+        }
+
+        for w in self.watches.iter() {
+            let (reg, hit) = match *w {
+                Watch::Change(r) => (r, before.get_reg(r) != self.cpu().get_reg(r)),
+                Watch::Value(r, v) => (r, before.get_reg(r) != v && self.cpu().get_reg(r) == v)
+            };
+
+            if hit {
+                return Stop::Watch {
+                    ip,
+                    reg,
+                    old: before.get_reg(reg),
+                    new: self.cpu().get_reg(reg)
+                };
+            }
+        }
+
+        Stop::Stepped
+    }
+
+    pub fn step_n(&mut self, n: usize) -> Stop {
+        for k in 0..n {
+            match self.step() {
+                Stop::Stepped => {}
+                stop => return stop
+            }
+
+            if k + 1 < n && self.breakpoints.contains(&self.cpu().ip()) {
+                return Stop::Breakpoint(self.cpu().ip());
+            }
+        }
+
+        Stop::Stepped
+    }
+
+    ///Runs until a breakpoint, a watchpoint, a fault or the end of the program.
+    ///A breakpoint at the current ip does not stop immediately.
+    pub fn cont(&mut self) -> Stop {
+        loop {
+            match self.step() {
+                Stop::Stepped => {}
+                stop => return stop
+            }
+
+            if self.breakpoints.contains(&self.cpu().ip()) {
+                return Stop::Breakpoint(self.cpu().ip());
+            }
+        }
+    }
+
+    ///Reads commands from `input` until `quit` or the end of the input
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut out: W) -> Result<()> {
+        self.print_listing(&mut out, 2)?;
+        write!(out, "(dbg) ")?;
+        out.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            match self.execute(line.trim(), &mut out) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => writeln!(out, "error: {}", e)?
+            }
+
+            write!(out, "(dbg) ")?;
+            out.flush()?;
+        }
+
+        writeln!(out)?;
+        Ok(())
+    }
+
+    ///Executes one command, returns false on quit
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> Result<bool> {
+        let mut parts = line.split_whitespace();
+        let cmd = match parts.next() {
+            Some(cmd) => cmd,
+            None => return Ok(true)
+        };
+        let args: Vec<&str> = parts.collect();
+
+        match cmd {
+            "s" | "step" => {
+                let n = match args.first() {
+                    Some(n) => n.parse()?,
+                    None => 1
+                };
+                let stop = self.step_n(n);
+                self.report(stop, out)?;
+            }
+            "c" | "continue" => {
+                let stop = self.cont();
+                self.report(stop, out)?;
+            }
+            "b" | "break" => {
+                let ix = parse_arg(&args, 0)?;
+                if ix >= self.program.ops.len() {
+                    return Err(format!("No instruction {}", ix).into());
+                }
+                self.add_breakpoint(ix);
+                writeln!(out, "breakpoint at {}", ix)?;
+            }
+            "d" | "delete" => {
+                let ix = parse_arg(&args, 0)?;
+                if !self.breakpoints.remove(&ix) {
+                    return Err(format!("No breakpoint at {}", ix).into());
+                }
+            }
+            "w" | "watch" => {
+                let r = self.parse_reg(&args, 0)?;
+                let w = match args.get(1) {
+                    Some(v) => Watch::Value(r, v.parse()?),
+                    None => Watch::Change(r)
+                };
+                self.add_watch(w);
+            }
+            "u" | "unwatch" => {
+                let r = self.parse_reg(&args, 0)?;
+                self.watches.retain(|w| match *w {
+                    Watch::Change(reg) | Watch::Value(reg, _) => reg != r
+                });
+            }
+            "set" => {
+                let r = self.parse_reg(&args, 0)?;
+                let v = parse_arg(&args, 1)?;
+                self.history.set_reg(r, v);
+                self.print_regs(out)?;
+            }
+            "ip" => {
+                let ix = parse_arg(&args, 0)?;
+                self.history.set_ip(ix);
+                self.print_listing(out, 2)?;
+            }
+            "back" => {
+                let n = match args.first() {
+                    Some(n) => n.parse()?,
+                    None => 1
+                };
+                let undone = (0..n).take_while(|_| self.history.back()).count();
+                if undone < n {
+                    writeln!(out, "undo log exhausted after {} instructions", undone)?;
+                }
+                self.print_listing(out, 2)?;
+            }
+            "save" => {
+                let path = args.first().ok_or("Missing file")?;
+                self.history.snapshot().save(path)?;
+            }
+            "load" => {
+                let path = args.first().ok_or("Missing file")?;
+                let s = Snapshot::load(path)?;
+                if s.cpu.reg_count() != self.program.reg_count() {
+                    return Err(format!("Snapshot has {} registers", s.cpu.reg_count()).into());
+                }
+                self.history.restore(&s);
+                self.print_listing(out, 2)?;
+            }
+            "export" => {
+                let path = args.first().ok_or("Missing file")?;
+                std::fs::write(path, self.program.to_string())?;
+            }
+            "decompile" => {
+                let code = decompile(self.program)?;
+                match args.first() {
+                    Some(path) => std::fs::write(path, code)?,
+                    None => write!(out, "{}", code)?
+                }
+            }
+            "cfg" => {
+                let cfg = Cfg::new(self.program);
+                match args.first() {
+                    Some(path) => std::fs::write(path, cfg.to_dot(self.program))?,
+                    None => write!(out, "{}", cfg)?
+                }
+            }
+            "f" | "find" => {
+                let r = self.parse_reg(&args, 0)?;
+                let v = parse_arg(&args, 1)?;
+                let limit = match args.get(2) {
+                    Some(n) => n.parse()?,
+                    None => FIND_LIMIT
+                };
+                match self.history.first_value(r, v, limit)? {
+                    Some(step) => writeln!(out, "r{} = {} first at step {}", r.index(), v, step)?,
+                    None => writeln!(out, "r{} never becomes {}", r.index(), v)?
+                }
+                self.print_listing(out, 2)?;
+            }
+            "r" | "regs" => self.print_regs(out)?,
+            "l" | "list" => {
+                let n = match args.first() {
+                    Some(n) => n.parse()?,
+                    None => 5
+                };
+                self.print_listing(out, n)?;
+            }
+            "i" | "info" => {
+                writeln!(out, "breakpoints: {:?}", self.breakpoints)?;
+                writeln!(out, "watchpoints: {:?}", self.watches)?;
+            }
+            "h" | "help" => writeln!(out, "{}", HELP)?,
+            "q" | "quit" => return Ok(false),
+            _ => return Err(format!("Unknown command {}, try help", cmd).into())
+        }
+
+        Ok(true)
+    }
+
+    fn parse_reg(&self, args: &[&str], ix: usize) -> Result<Reg> {
+        let s = args.get(ix).ok_or("Missing register")?;
+        let n = s.trim_start_matches('r').parse()?;
+        Reg::new(n, self.cpu().reg_count())
+    }
+
+    fn report<W: Write>(&self, stop: Stop, out: &mut W) -> Result<()> {
+        match stop {
+            Stop::Breakpoint(ix) => writeln!(out, "breakpoint at {}", ix)?,
+            Stop::Watch { ip, reg, old, new } => {
+                writeln!(out, "watch r{}: {} -> {} at {}", reg.index(), old, new, ip)?
+            }
+            Stop::Halted => writeln!(out, "halted")?,
+            Stop::Fault { ip, fault } => writeln!(out, "{} at {}", fault, ip)?,
+            Stop::Stepped => {}
+        }
+
+        self.print_listing(out, 2)
+    }
+
+    fn print_regs<W: Write>(&self, out: &mut W) -> Result<()> {
+        writeln!(out, "ip={} {:?} steps={}", self.cpu().ip(), self.cpu().registers(), self.steps())?;
+        Ok(())
+    }
+
+    fn print_listing<W: Write>(&self, out: &mut W, around: usize) -> Result<()> {
+        self.print_regs(out)?;
+
+        let ip = self.cpu().ip();
+        let from = ip.saturating_sub(around);
+        let to = (ip + around + 1).min(self.program.ops.len());
+
+        for ix in from..to {
+            let marker = if ix == ip { "=>" } else { "  " };
+            let bp = if self.breakpoints.contains(&ix) { "*" } else { " " };
+            writeln!(out, "{}{}{:4}  {}", marker, bp, ix, self.program.ops[ix])?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(args: &[&str], ix: usize) -> Result<T>
+    where T::Err: std::error::Error + 'static {
+    let s = args.get(ix).ok_or("Missing argument")?;
+    Ok(s.parse()?)
+}
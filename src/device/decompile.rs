@@ -0,0 +1,555 @@
+use device::flow::blocks;
+use device::flow::resolved_expr;
+use device::flow::BinOp;
+use device::flow::Block;
+use device::flow::Exit;
+use device::flow::Expr;
+use device::flow::Operand;
+use device::Program;
+use device::Reg;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use Result;
+
+///Turns `#ip` programs into structured pseudo-code
+pub struct Decompiler<'a> {
+    program: &'a Program,
+    names: Vec<String>
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Cond {
+    Cmp(BinOp, Operand, Operand),
+    NonZero(Reg)
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Term {
+    ///Addresses outside the program halt
+    Goto(usize),
+    Branch { cond: Cond, taken: usize, other: usize },
+    Computed(Expr)
+}
+
+#[derive(Debug)]
+struct Node {
+    start: usize,
+    stmts: Vec<(Reg, Expr)>,
+    term: Term
+}
+
+enum Line {
+    Label(usize),
+    Text(usize, String),
+    Goto(usize, String, usize)
+}
+
+struct Emitter<'a> {
+    nodes: Vec<Node>,
+    index: HashMap<usize, usize>,
+    preds: Vec<Vec<usize>>,
+    latches: HashMap<usize, usize>,
+    len: usize,
+    names: &'a [String],
+    open: Vec<(usize, usize)>,
+    lines: Vec<Line>
+}
+
+type RegSet = u64;
+
+///Most registers the decompiler handles, one bit of a `RegSet` each
+pub const MAX_REGS: usize = RegSet::BITS as usize;
+
+fn bit(r: Reg) -> RegSet {
+    1 << r.index()
+}
+
+///Set of the first `n` registers
+fn all(n: usize) -> RegSet {
+    match n {
+        MAX_REGS => RegSet::MAX,
+        n => (1 << n) - 1
+    }
+}
+
+impl<'a> Decompiler<'a> {
+    pub fn new(program: &'a Program) -> Result<Decompiler<'a>> {
+        if program.reg_count() > MAX_REGS {
+            return Err(format!("Decompiler supports at most {} registers", MAX_REGS).into());
+        }
+
+        let names = (0..program.reg_count())
+            .map(|i| match program.ip_reg {
+                Some(ip) if ip.index() == i => "ip".to_string(),
+                _ => format!("r{}", i)
+            })
+            .collect();
+
+        Ok(Decompiler {
+            program,
+            names
+        })
+    }
+
+    ///Symbolic register names, missing entries keep their default
+    pub fn with_names(mut self, names: &[&str]) -> Decompiler<'a> {
+        for (n, name) in self.names.iter_mut().zip(names.iter()) {
+            *n = name.to_string();
+        }
+        self
+    }
+
+    pub fn decompile(&self) -> String {
+        let nodes = self.nodes();
+        let mut emitter = Emitter::new(nodes, self.program.ops.len(), &self.names);
+        emitter.run();
+        emitter.render()
+    }
+
+    fn nodes(&self) -> Vec<Node> {
+        let p = self.program;
+        let blocks = blocks(p);
+        let live_in = self.liveness(&blocks);
+        let all = all(p.reg_count());
+        let live_at = |addr: usize| -> RegSet {
+            blocks.iter()
+                .position(|b| b.start == addr)
+                .map(|ix| live_in[ix])
+                .unwrap_or(all)
+        };
+
+        let mut nodes: Vec<Node> = blocks.iter()
+            .map(|b| {
+                let mut stmts: Vec<(Reg, Expr)> = (b.start..b.end)
+                    .filter(|&i| Some(p.ops[i].target()) != p.ip_reg)
+                    .map(|i| (p.ops[i].target(), resolved_expr(p, i)))
+                    .collect();
+
+                let term = match b.exit {
+                    Exit::Next => Term::Goto(b.end),
+                    Exit::Jump(t) => Term::Goto(t),
+                    Exit::Computed(e) => Term::Computed(e),
+                    Exit::Skip(x) => {
+                        let (taken, other) = (b.end + 1, b.end);
+                        let dead = live_at(taken) & bit(x) == 0 && live_at(other) & bit(x) == 0;
+
+                        //Fold the comparison into the branch if its result is not used later
+                        let cond = match stmts.last() {
+                            Some(&(r, Expr::Bin(op, a, c))) if r == x && op.is_comparison() && dead => {
+                                stmts.pop();
+                                Cond::Cmp(op, a, c)
+                            }
+                            _ => Cond::NonZero(x)
+                        };
+
+                        Term::Branch { cond, taken, other }
+                    }
+                };
+
+                Node {
+                    start: b.start,
+                    stmts,
+                    term
+                }
+            })
+            .collect();
+
+        //Forward jumps through blocks that only contain a goto. Blocks nothing
+        //jumps to may be the target of a computed jump and are kept.
+        let jumped_to: BTreeSet<usize> = nodes.iter()
+            .flat_map(|n| targets(&n.term))
+            .collect();
+
+        let forward: HashMap<usize, usize> = nodes.iter()
+            .filter(|n| n.start != 0 && n.stmts.is_empty() && jumped_to.contains(&n.start))
+            .filter_map(|n| match n.term {
+                Term::Goto(t) if t != n.start && t < p.ops.len() => Some((n.start, t)),
+                _ => None
+            })
+            .collect();
+
+        let resolve = |mut addr: usize| {
+            let mut steps = 0;
+            while let Some(&t) = forward.get(&addr) {
+                addr = t;
+                steps += 1;
+                if steps > forward.len() {
+                    break;
+                }
+            }
+            addr
+        };
+
+        for n in nodes.iter_mut() {
+            n.term = match n.term {
+                Term::Goto(t) => Term::Goto(resolve(t)),
+                Term::Branch { cond, taken, other } => Term::Branch {
+                    cond,
+                    taken: resolve(taken),
+                    other: resolve(other)
+                },
+                t => t
+            };
+        }
+
+        nodes.retain(|n| !forward.contains_key(&n.start));
+        nodes
+    }
+
+    fn liveness(&self, blocks: &[Block]) -> Vec<RegSet> {
+        let p = self.program;
+        let all = all(p.reg_count());
+        let ip = p.ip_reg.map(bit).unwrap_or(0);
+
+        //Registers read before written and registers written per block
+        let (uses, defs): (Vec<RegSet>, Vec<RegSet>) = blocks.iter()
+            .map(|b| {
+                let (mut uses, mut defs) = (0, 0);
+                for i in b.start..b.end {
+                    for o in resolved_expr(p, i).operands() {
+                        if let Some(r) = o.reg() {
+                            uses |= bit(r) & !defs;
+                        }
+                    }
+                    defs |= bit(p.ops[i].target());
+                }
+                (uses & !ip, defs & !ip)
+            })
+            .unzip();
+
+        let index: HashMap<usize, usize> = blocks.iter()
+            .enumerate()
+            .map(|(ix, b)| (b.start, ix))
+            .collect();
+
+        let mut live_in = vec![0; blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for ix in (0..blocks.len()).rev() {
+                let out = match blocks[ix].successors() {
+                    Some(succ) => succ.iter()
+                        .map(|s| index.get(s).map(|&s| live_in[s]).unwrap_or(all))
+                        .fold(0, |acc, l| acc | l),
+                    None => all
+                };
+
+                let l = uses[ix] | (out & !defs[ix]);
+                if l != live_in[ix] {
+                    live_in[ix] = l;
+                    changed = true;
+                }
+            }
+        }
+
+        live_in
+    }
+}
+
+pub fn decompile(p: &Program) -> Result<String> {
+    Ok(Decompiler::new(p)?.decompile())
+}
+
+impl<'a> Emitter<'a> {
+    fn new(nodes: Vec<Node>, len: usize, names: &'a [String]) -> Emitter<'a> {
+        let index: HashMap<usize, usize> = nodes.iter()
+            .enumerate()
+            .map(|(ix, n)| (n.start, ix))
+            .collect();
+
+        let mut preds = vec![Vec::new(); nodes.len()];
+        for (ix, n) in nodes.iter().enumerate() {
+            for t in targets(&n.term) {
+                if let Some(&t) = index.get(&t) {
+                    preds[t].push(ix);
+                }
+            }
+        }
+
+        let mut emitter = Emitter {
+            nodes,
+            index,
+            preds,
+            latches: HashMap::new(),
+            len,
+            names,
+            open: Vec::new(),
+            lines: Vec::new()
+        };
+        emitter.find_loops();
+        emitter
+    }
+
+    fn next_addr(&self, ix: usize) -> usize {
+        self.nodes.get(ix + 1)
+            .map(|n| n.start)
+            .unwrap_or(self.len)
+    }
+
+    ///Only edges from inside [from, to) may enter the nodes after `from`
+    fn single_entry(&self, from: usize, to: usize) -> bool {
+        (from + 1..to).all(|ix| self.preds[ix].iter().all(|&p| p >= from && p < to))
+    }
+
+    fn find_loops(&mut self) {
+        for latch in 0..self.nodes.len() {
+            let next = self.next_addr(latch);
+            let header = match self.nodes[latch].term {
+                Term::Goto(t) => t,
+                Term::Branch { taken, other, .. } if other == next => taken,
+                Term::Branch { taken, other, .. } if taken == next => other,
+                _ => continue
+            };
+
+            let h = match self.index.get(&header) {
+                Some(&h) if h <= latch => h,
+                _ => continue
+            };
+
+            if self.single_entry(h, latch + 1) {
+                //Later latches give the outermost loop
+                self.latches.insert(h, latch);
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        let n = self.nodes.len();
+        self.emit(0, n, 1);
+    }
+
+    fn emit(&mut self, from: usize, to: usize, depth: usize) {
+        let mut ix = from;
+        while ix < to {
+            let is_open = self.open.iter().any(|&(h, _)| h == ix);
+            if !is_open {
+                if let Some(&latch) = self.latches.get(&ix) {
+                    if latch < to {
+                        self.emit_loop(ix, latch, depth);
+                        ix = latch + 1;
+                        continue;
+                    }
+                }
+                self.lines.push(Line::Label(self.nodes[ix].start));
+            }
+
+            for k in 0..self.nodes[ix].stmts.len() {
+                let (r, e) = self.nodes[ix].stmts[k];
+                let s = self.assignment(r, &e);
+                self.lines.push(Line::Text(depth, s));
+            }
+
+            if self.open.last().map(|&(_, l)| l) == Some(ix) {
+                //The latch branch is printed by the loop
+                ix += 1;
+                continue;
+            }
+
+            ix = self.emit_term(ix, to, depth);
+        }
+    }
+
+    fn emit_loop(&mut self, header: usize, latch: usize, depth: usize) {
+        let start = self.nodes[header].start;
+        let head = match self.nodes[latch].term {
+            Term::Goto(_) => "loop {",
+            _ => "do {"
+        };
+
+        self.lines.push(Line::Label(start));
+        self.lines.push(Line::Text(depth, head.to_string()));
+
+        self.open.push((header, latch));
+        self.emit(header, latch + 1, depth + 1);
+        self.open.pop();
+
+        let tail = match self.nodes[latch].term {
+            Term::Branch { cond, taken, .. } => {
+                format!("}} while ({})", self.cond(cond, taken != start))
+            }
+            _ => "}".to_string()
+        };
+        self.lines.push(Line::Text(depth, tail));
+    }
+
+    ///Emits the terminator of node `ix`, returns the next node to emit
+    fn emit_term(&mut self, ix: usize, to: usize, depth: usize) -> usize {
+        let next = self.next_addr(ix);
+
+        match self.nodes[ix].term {
+            Term::Goto(t) => {
+                if t != next {
+                    self.goto(depth, String::new(), t);
+                }
+            }
+
+            Term::Computed(e) => {
+                let s = match e {
+                    Expr::Bin(BinOp::Add, Operand::Imm(k), o) | Expr::Bin(BinOp::Add, o, Operand::Imm(k)) => {
+                        format!("goto {} + {}", k + 1, self.operand(o))
+                    }
+                    e => format!("goto {} + 1", self.expr(&e))
+                };
+                self.lines.push(Line::Text(depth, s));
+            }
+
+            Term::Branch { cond, taken, other } => {
+                //Forward branch over a single entry region becomes an if
+                let body = if other == next && taken > next {
+                    Some((taken, true))
+                } else if taken == next && other > next {
+                    Some((other, false))
+                } else {
+                    None
+                };
+
+                let body = body.and_then(|(end, negate)| {
+                    let j = *self.index.get(&end)?;
+
+                    //The body must not swallow the latch of a surrounding loop
+                    let has_latch = self.open.iter().any(|&(_, l)| l > ix && l < j);
+
+                    if j <= to && !has_latch && self.single_entry(ix, j) {
+                        Some((j, negate))
+                    } else {
+                        None
+                    }
+                });
+
+                match body {
+                    Some((j, negate)) => {
+                        let s = format!("if ({}) {{", self.cond(cond, negate));
+                        self.lines.push(Line::Text(depth, s));
+                        self.emit(ix + 1, j, depth + 1);
+                        self.lines.push(Line::Text(depth, "}".to_string()));
+                        return j;
+                    }
+                    None if other == next => {
+                        let c = format!("if ({}) ", self.cond(cond, false));
+                        self.goto(depth, c, taken);
+                    }
+                    None if taken == next => {
+                        let c = format!("if ({}) ", self.cond(cond, true));
+                        self.goto(depth, c, other);
+                    }
+                    None => {
+                        let c = format!("if ({}) ", self.cond(cond, false));
+                        self.goto(depth, c, taken);
+                        self.goto(depth, String::new(), other);
+                    }
+                }
+            }
+        }
+
+        ix + 1
+    }
+
+    fn goto(&mut self, depth: usize, prefix: String, target: usize) {
+        let loop_exit = self.open.last()
+            .map(|&(_, latch)| self.next_addr(latch));
+
+        if loop_exit == Some(target) {
+            self.lines.push(Line::Text(depth, format!("{}break", prefix)));
+        } else if target >= self.len {
+            self.lines.push(Line::Text(depth, format!("{}halt", prefix)));
+        } else {
+            self.lines.push(Line::Goto(depth, prefix, target));
+        }
+    }
+
+    fn operand(&self, o: Operand) -> String {
+        match o {
+            Operand::Reg(r) => self.names[r.index()].clone(),
+            Operand::Imm(v) => v.to_string()
+        }
+    }
+
+    fn expr(&self, e: &Expr) -> String {
+        match *e {
+            Expr::Value(a) => self.operand(a),
+            Expr::Bin(op, a, b) => {
+                let s = format!("{} {} {}", self.operand(a), op_str(op, false), self.operand(b));
+                if op.is_comparison() {
+                    format!("({})", s)
+                } else {
+                    s
+                }
+            }
+        }
+    }
+
+    fn assignment(&self, r: Reg, e: &Expr) -> String {
+        let name = &self.names[r.index()];
+        match *e {
+            Expr::Bin(op, Operand::Reg(a), b) if a == r && !op.is_comparison() => {
+                format!("{} {}= {}", name, op_str(op, false), self.operand(b))
+            }
+            _ => format!("{} = {}", name, self.expr(e))
+        }
+    }
+
+    fn cond(&self, c: Cond, negate: bool) -> String {
+        match c {
+            Cond::Cmp(op, a, b) => {
+                format!("{} {} {}", self.operand(a), op_str(op, negate), self.operand(b))
+            }
+            Cond::NonZero(r) => {
+                let cmp = if negate { "==" } else { "!=" };
+                format!("{} {} 0", self.names[r.index()], cmp)
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        //Blocks without predecessors can only be reached by computed jumps
+        let mut labels: BTreeSet<usize> = self.lines.iter()
+            .filter_map(|l| match *l {
+                Line::Goto(_, _, t) => Some(t),
+                _ => None
+            })
+            .collect();
+        labels.extend(self.nodes.iter()
+            .enumerate()
+            .filter(|&(ix, n)| n.start != 0 && self.preds[ix].is_empty())
+            .map(|(_, n)| n.start));
+
+        let mut out = String::new();
+        for l in self.lines.iter() {
+            match l {
+                Line::Label(addr) if labels.contains(addr) => {
+                    out.push_str(&format!("L{}:\n", addr));
+                }
+                Line::Label(_) => {}
+                Line::Text(depth, s) => {
+                    out.push_str(&format!("{}{}\n", "    ".repeat(*depth), s));
+                }
+                Line::Goto(depth, prefix, t) => {
+                    out.push_str(&format!("{}{}goto L{}\n", "    ".repeat(*depth), prefix, t));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn targets(t: &Term) -> Vec<usize> {
+    match *t {
+        Term::Goto(t) => vec![t],
+        Term::Branch { taken, other, .. } => vec![taken, other],
+        Term::Computed(_) => vec![]
+    }
+}
+
+fn op_str(op: BinOp, negate: bool) -> &'static str {
+    match (op, negate) {
+        (BinOp::Add, _) => "+",
+        (BinOp::Mul, _) => "*",
+        (BinOp::And, _) => "&",
+        (BinOp::Or, _) => "|",
+        (BinOp::Gt, false) => ">",
+        (BinOp::Gt, true) => "<=",
+        (BinOp::Eq, false) => "==",
+        (BinOp::Eq, true) => "!="
+    }
+}
@@ -0,0 +1,234 @@
+use device::opcode::MNEMONICS;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+///Opcode number of a sample and the reference opcode indices matching its effect
+pub type Options = (usize, BTreeSet<usize>);
+
+///Mapping from opcode numbers to reference opcode indices
+pub type Table = Vec<usize>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deduction {
+    Unique(Table),
+    ///Consistent mappings, `complete` is false if there were more than the limit
+    Ambiguous { mappings: Vec<Table>, complete: bool },
+    ///The opcode numbers can not be mapped to distinct opcodes,
+    ///the listed sample indices already contradict each other
+    Conflict { numbers: Vec<usize>, samples: Vec<usize> }
+}
+
+///Finds the mappings of `n` opcode numbers to `n` opcodes consistent
+///with all samples. Lists at most `limit` mappings if there are several.
+pub fn deduce(samples: &[Options], n: usize, limit: usize) -> Deduction {
+    let full: BTreeSet<usize> = (0..n).collect();
+    let mut domains = vec![full; n];
+
+    for (ix, (num, cands)) in samples.iter().enumerate() {
+        match domains.get_mut(*num) {
+            Some(d) => *d = d.intersection(cands).cloned().collect(),
+            None => return Deduction::Conflict { numbers: vec![*num], samples: vec![ix] }
+        }
+    }
+
+    if let Some((numbers, reachable)) = hall_violation(&domains) {
+        return Deduction::Conflict {
+            samples: explain(samples, &numbers, &reachable, n),
+            numbers
+        };
+    }
+
+    propagate(&mut domains);
+
+    let mut mappings = Vec::new();
+    let mut used = vec![false; n];
+    let mut table = vec![None; n];
+    enumerate(&domains, &mut table, &mut used, &mut mappings, limit + 1);
+
+    if mappings.len() == 1 {
+        return Deduction::Unique(mappings.remove(0));
+    }
+
+    let complete = mappings.len() <= limit;
+    mappings.truncate(limit);
+    Deduction::Ambiguous { mappings, complete }
+}
+
+///Kuhn's augmenting path for number `u`
+fn augment(u: usize, domains: &[BTreeSet<usize>], owner: &mut [Option<usize>], seen: &mut [bool]) -> bool {
+    for &op in domains[u].iter() {
+        if seen[op] {
+            continue;
+        }
+        seen[op] = true;
+
+        if owner[op].is_none_or(|v| augment(v, domains, owner, seen)) {
+            owner[op] = Some(u);
+            return true;
+        }
+    }
+
+    false
+}
+
+///Numbers that together have fewer candidate opcodes than their count,
+///with those opcodes. None if a perfect matching exists.
+fn hall_violation(domains: &[BTreeSet<usize>]) -> Option<(Vec<usize>, BTreeSet<usize>)> {
+    let n = domains.len();
+    let mut owner = vec![None; n];
+
+    let unmatched = (0..n).find(|&u| !augment(u, domains, &mut owner, &mut vec![false; n]))?;
+
+    //Alternating search from the unmatched number, every reached opcode
+    //is matched, so fewer opcodes than numbers are reached
+    let mut numbers = BTreeSet::new();
+    let mut reachable = BTreeSet::new();
+    let mut stack = vec![unmatched];
+
+    while let Some(u) = stack.pop() {
+        if !numbers.insert(u) {
+            continue;
+        }
+
+        for &op in domains[u].iter() {
+            if reachable.insert(op) {
+                if let Some(v) = owner[op] {
+                    stack.push(v);
+                }
+            }
+        }
+    }
+
+    Some((numbers.into_iter().collect(), reachable))
+}
+
+///Greedily picks samples per number until its candidates are within `allowed`
+fn explain(samples: &[Options], numbers: &[usize], allowed: &BTreeSet<usize>, n: usize) -> Vec<usize> {
+    let mut picked = Vec::new();
+
+    for &num in numbers {
+        let own: Vec<usize> = (0..samples.len())
+            .filter(|&ix| samples[ix].0 == num)
+            .collect();
+        let mut current: BTreeSet<usize> = (0..n).collect();
+
+        while !current.is_subset(allowed) {
+            let best = own.iter()
+                .cloned()
+                .max_by_key(|&ix| current.difference(&samples[ix].1).count());
+
+            match best {
+                Some(ix) if current.difference(&samples[ix].1).count() > 0 => {
+                    current = current.intersection(&samples[ix].1).cloned().collect();
+                    picked.push(ix);
+                }
+                _ => break
+            }
+        }
+    }
+
+    picked.sort();
+    picked
+}
+
+///Removes candidates that no mapping can use: opcodes already fixed to
+///another number, and numbers that are the only option of an opcode
+fn propagate(domains: &mut [BTreeSet<usize>]) {
+    let n = domains.len();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for u in 0..n {
+            if domains[u].len() != 1 {
+                continue;
+            }
+
+            let op = *domains[u].iter().next().unwrap();
+            for (v, d) in domains.iter_mut().enumerate() {
+                if v != u && d.remove(&op) {
+                    changed = true;
+                }
+            }
+        }
+
+        for op in 0..n {
+            let owners: Vec<usize> = (0..n)
+                .filter(|&u| domains[u].contains(&op))
+                .collect();
+
+            if let [u] = owners[..] {
+                if domains[u].len() > 1 {
+                    domains[u] = Some(op).into_iter().collect();
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+fn enumerate(domains: &[BTreeSet<usize>], table: &mut Vec<Option<usize>>, used: &mut Vec<bool>,
+             out: &mut Vec<Table>, max: usize) {
+    if out.len() >= max {
+        return;
+    }
+
+    //Most constrained number first
+    let next = (0..domains.len())
+        .filter(|&u| table[u].is_none())
+        .min_by_key(|&u| domains[u].iter().filter(|&&op| !used[op]).count());
+
+    let u = match next {
+        Some(u) => u,
+        None => {
+            out.push(table.iter().map(|o| o.unwrap()).collect());
+            return;
+        }
+    };
+
+    for &op in domains[u].iter() {
+        if used[op] {
+            continue;
+        }
+
+        used[op] = true;
+        table[u] = Some(op);
+        enumerate(domains, table, used, out, max);
+        table[u] = None;
+        used[op] = false;
+    }
+}
+
+fn fmt_table(f: &mut Formatter, table: &[usize]) -> std::fmt::Result {
+    let names: Vec<String> = table.iter()
+        .enumerate()
+        .map(|(num, &op)| format!("{}={}", num, MNEMONICS[op]))
+        .collect();
+
+    writeln!(f, "{}", names.join(" "))
+}
+
+impl Display for Deduction {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Deduction::Unique(table) => {
+                write!(f, "unique mapping: ")?;
+                fmt_table(f, table)
+            }
+            Deduction::Ambiguous { mappings, complete } => {
+                writeln!(f, "{}{} consistent mappings:",
+                         if *complete { "" } else { "more than " }, mappings.len())?;
+                for t in mappings.iter() {
+                    fmt_table(f, t)?;
+                }
+                Ok(())
+            }
+            Deduction::Conflict { numbers, samples } => {
+                writeln!(f, "no consistent mapping, opcode numbers {:?} conflict in samples {:?}",
+                         numbers, samples)
+            }
+        }
+    }
+}
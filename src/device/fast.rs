@@ -0,0 +1,349 @@
+use device::flow::resolved_expr;
+use device::flow::BinOp;
+use device::flow::Expr;
+use device::flow::Operand;
+use device::fault::Fault;
+use device::fault::Outcome;
+use device::fault::Overflow;
+use device::fault::Reason;
+use device::Cpu;
+use device::Opcode;
+use device::Program;
+use device::Reg;
+use util::diff::differential;
+use util::diff::shrink_u64;
+use util::diff::shrink_vec;
+use util::diff::Mismatch;
+use Result;
+
+///Most registers a program of the fast interpreter can have
+pub const MAX_REGS: usize = 8;
+
+///Size of the internal register file. Indices are masked with `SLOTS - 1`,
+///so no bounds checks happen at run time. Programs without an ip register
+///write the ip to the unused slot `MAX_REGS`.
+const SLOTS: usize = 2 * MAX_REGS;
+const MASK: usize = SLOTS - 1;
+
+///Most chunks of a lockstep case
+const MAX_CHUNKS: i64 = 8;
+
+///Register 0 and the instruction counts of the chunks to run from there
+pub type Chunks = (u64, Vec<u64>);
+
+///Cpu and outcome after each chunk
+pub type Trace = Vec<(Cpu, Outcome)>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    AddRR, AddRI,
+    MulRR, MulRI,
+    AndRR, AndRI,
+    OrRR, OrRI,
+    SetR, SetI,
+    GtIR, GtRI, GtRR,
+    EqRI, EqRR,
+    ///Constant operands whose sum or product overflows
+    Trap,
+}
+
+///Pre-decoded instruction: reads of the ip register are replaced by
+///immediates and register operands are already validated
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Op {
+    kind: Kind,
+    a: u64,
+    b: u64,
+    c: usize
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Instr {
+    op: Op,
+    ///Writes the ip register
+    jumps: bool,
+    ///Comparison into `x` directly followed by `addr x ip ip`, both run
+    ///as one superinstruction
+    skip: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct FastProgram {
+    code: Vec<Instr>,
+    ///Original instructions, to report faults
+    source: Vec<Opcode>,
+    ip_slot: usize,
+    regs: usize,
+    overflow: Overflow
+}
+
+fn decode(p: &Program, i: usize) -> Op {
+    use self::Kind::*;
+    use self::Operand::Imm;
+    use self::Operand::Reg as R;
+
+    let c = p.ops[i].target();
+    let r = |r: Reg| r.index() as u64;
+
+    let e = resolved_expr(p, i);
+    if let Some(v) = e.constant() {
+        //Folding wraps, the program may not
+        let v = match e {
+            Expr::Bin(BinOp::Add, Imm(a), Imm(b)) => p.overflow().add(a, b),
+            Expr::Bin(BinOp::Mul, Imm(a), Imm(b)) => p.overflow().mul(a, b),
+            _ => Some(v)
+        };
+
+        return match v {
+            Some(v) => Op { kind: SetI, a: v, b: 0, c: c.index() },
+            None => Op { kind: Trap, a: 0, b: 0, c: c.index() }
+        };
+    }
+
+    let (kind, a, b) = match e {
+        Expr::Value(R(a)) => (SetR, r(a), 0),
+        Expr::Bin(op, x, y) => {
+            //Commutative operations take the immediate second
+            let (x, y) = match (op, x, y) {
+                (BinOp::Add, Imm(_), R(_)) | (BinOp::Mul, Imm(_), R(_)) |
+                (BinOp::And, Imm(_), R(_)) | (BinOp::Or, Imm(_), R(_)) |
+                (BinOp::Eq, Imm(_), R(_)) => (y, x),
+                _ => (x, y)
+            };
+
+            match (op, x, y) {
+                (BinOp::Add, R(a), R(b)) => (AddRR, r(a), r(b)),
+                (BinOp::Add, R(a), Imm(b)) => (AddRI, r(a), b),
+                (BinOp::Mul, R(a), R(b)) => (MulRR, r(a), r(b)),
+                (BinOp::Mul, R(a), Imm(b)) => (MulRI, r(a), b),
+                (BinOp::And, R(a), R(b)) => (AndRR, r(a), r(b)),
+                (BinOp::And, R(a), Imm(b)) => (AndRI, r(a), b),
+                (BinOp::Or, R(a), R(b)) => (OrRR, r(a), r(b)),
+                (BinOp::Or, R(a), Imm(b)) => (OrRI, r(a), b),
+                (BinOp::Gt, Imm(a), R(b)) => (GtIR, a, r(b)),
+                (BinOp::Gt, R(a), Imm(b)) => (GtRI, r(a), b),
+                (BinOp::Gt, R(a), R(b)) => (GtRR, r(a), r(b)),
+                (BinOp::Eq, R(a), Imm(b)) => (EqRI, r(a), b),
+                (BinOp::Eq, R(a), R(b)) => (EqRR, r(a), r(b)),
+                _ => unreachable!("constant expressions are folded")
+            }
+        }
+        Expr::Value(Imm(_)) => unreachable!("constant expressions are folded")
+    };
+
+    Op { kind, a, b, c: c.index() }
+}
+
+fn is_comparison(op: &Op) -> bool {
+    use self::Kind::*;
+    matches!(op.kind, GtIR | GtRI | GtRR | EqRI | EqRR)
+}
+
+impl FastProgram {
+    pub fn compile(p: &Program) -> Result<FastProgram> {
+        if p.reg_count() > MAX_REGS {
+            return Err(format!("Fast interpreter supports at most {} registers", MAX_REGS).into());
+        }
+
+        let ops: Vec<Op> = (0..p.ops.len())
+            .map(|i| decode(p, i))
+            .collect();
+        let ip_slot = p.ip_reg.map_or(MAX_REGS, |r| r.index());
+
+        let code = ops.iter()
+            .enumerate()
+            .map(|(i, &op)| {
+                let skip = match ops.get(i + 1) {
+                    Some(jump) => op.c != ip_slot
+                        && is_comparison(&op)
+                        && jump.kind == Kind::AddRI
+                        && jump.a == op.c as u64
+                        && jump.b == (i + 1) as u64
+                        && jump.c == ip_slot,
+                    None => false
+                };
+
+                Instr { op, jumps: op.c == ip_slot, skip }
+            })
+            .collect();
+
+        Ok(FastProgram {
+            code,
+            source: p.ops.clone(),
+            ip_slot,
+            regs: p.reg_count(),
+            overflow: p.overflow()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    pub fn cpu(&self) -> Cpu {
+        Cpu::new(self.regs)
+    }
+
+    ///Runs until the program halts, faults or executed `limit` instructions
+    pub fn run_with_cpu(&self, cpu: &mut Cpu, limit: Option<u64>) -> Outcome {
+        let (steps, fault) = self.run_until(cpu, None, limit.unwrap_or(u64::MAX));
+
+        //Like the reference interpreter, the limit is checked before halting
+        let reason = match (fault, limit) {
+            (Some(fault), _) => Reason::Fault { ip: cpu.ip(), fault },
+            (None, Some(l)) if steps >= l => Reason::Fault { ip: cpu.ip(), fault: Fault::StepLimit(l) },
+            _ => Reason::Halted
+        };
+
+        Outcome { reason, steps }
+    }
+
+    pub fn run(&self, limit: Option<u64>) -> (Cpu, Outcome) {
+        let mut cpu = self.cpu();
+        let outcome = self.run_with_cpu(&mut cpu, limit);
+        (cpu, outcome)
+    }
+
+    ///Runs at most `limit` instructions. Stops before executing the
+    ///instruction at `breakpoint`, even if it is the current one.
+    ///Returns the executed instruction count, counting fused instructions
+    ///individually, and the fault of the instruction at the ip if it stopped
+    ///there. Leaves `cpu` in the state of the reference interpreter.
+    pub fn run_until(&self, cpu: &mut Cpu, breakpoint: Option<usize>, limit: u64) -> (u64, Option<Fault>) {
+        let mut reg = [0u64; SLOTS];
+        reg[..cpu.reg_count()].copy_from_slice(cpu.registers());
+
+        let bp = breakpoint.unwrap_or(usize::MAX);
+        let ip = self.ip_slot & MASK;
+        let mut pc = cpu.ip();
+        let mut last = None;
+        let mut steps = 0;
+        let mut fault = None;
+
+        while pc < self.code.len() && pc != bp && steps < limit {
+            let instr = &self.code[pc];
+            let op = &instr.op;
+            let v = match eval(op, &reg, self.overflow) {
+                Some(v) => v,
+                None => {
+                    fault = Some(Fault::Overflow(self.source[pc]));
+                    break;
+                }
+            };
+            reg[op.c & MASK] = v;
+            last = Some(pc);
+
+            if instr.skip && limit - steps >= 2 && pc + 1 != bp {
+                let target = (pc + 1) as u64 + v;
+                reg[ip] = target;
+                last = Some(pc + 1);
+                pc = target as usize + 1;
+                steps += 2;
+            } else if instr.jumps {
+                pc = (v as usize).saturating_add(1);
+                steps += 1;
+            } else {
+                pc += 1;
+                steps += 1;
+            }
+        }
+
+        //The ip register is only written by jumps, the reference
+        //interpreter leaves the index of the last instruction in it.
+        //A faulting instruction has been written there before it ran.
+        if fault.is_some() {
+            reg[ip] = pc as u64;
+        } else if let Some(last) = last {
+            if !self.code[last].jumps {
+                reg[ip] = last as u64;
+            }
+        }
+
+        for (i, v) in reg[..cpu.reg_count()].iter().enumerate() {
+            cpu.set_reg(Reg(i), *v);
+        }
+        cpu.set_ip(pc);
+
+        (steps, fault)
+    }
+}
+
+#[inline(always)]
+fn eval(op: &Op, reg: &[u64; SLOTS], overflow: Overflow) -> Option<u64> {
+    use self::Kind::*;
+    let r = |i: u64| reg[i as usize & MASK];
+
+    match op.kind {
+        AddRR => overflow.add(r(op.a), r(op.b)),
+        AddRI => overflow.add(r(op.a), op.b),
+        MulRR => overflow.mul(r(op.a), r(op.b)),
+        MulRI => overflow.mul(r(op.a), op.b),
+        AndRR => Some(r(op.a) & r(op.b)),
+        AndRI => Some(r(op.a) & op.b),
+        OrRR => Some(r(op.a) | r(op.b)),
+        OrRI => Some(r(op.a) | op.b),
+        SetR => Some(r(op.a)),
+        SetI => Some(op.a),
+        GtIR => Some((op.a > r(op.b)) as u64),
+        GtRI => Some((r(op.a) > op.b) as u64),
+        GtRR => Some((r(op.a) > r(op.b)) as u64),
+        EqRI => Some((r(op.a) == op.b) as u64),
+        EqRR => Some((r(op.a) == r(op.b)) as u64),
+        Trap => None,
+    }
+}
+
+///Runs the chunks one after another until the program halts or faults
+fn trace<F>(mut cpu: Cpu, chunks: &[u64], mut run: F) -> Trace
+    where F: FnMut(&mut Cpu, Option<u64>) -> Outcome {
+    let mut trace = Vec::new();
+
+    for &chunk in chunks {
+        let outcome = run(&mut cpu, Some(chunk));
+        trace.push((cpu.clone(), outcome));
+
+        if !matches!(outcome.reason, Reason::Fault { fault: Fault::StepLimit(_), .. }) {
+            break;
+        }
+    }
+
+    trace
+}
+
+///Runs the fast and the reference interpreter side by side on `cases` values
+///of register 0 up to `max_reg0`, comparing the state after each random chunk
+///of at most `max_chunk` instructions
+pub fn lockstep(p: &Program, cases: usize, seed: u64, max_reg0: u64, max_chunk: u64)
+                -> Result<Option<Mismatch<Chunks, Trace>>> {
+    let fast = FastProgram::compile(p)?;
+    let reg0 = Reg::new(0, p.reg_count())?;
+    let start = |r: u64| {
+        let mut cpu = p.cpu();
+        cpu.set_reg(reg0, r);
+        cpu
+    };
+
+    let m = differential(cases, seed,
+                         |rng| {
+                             let chunks = (0..rng.range(1, MAX_CHUNKS))
+                                 .map(|_| rng.range(1, max_chunk as i64) as u64)
+                                 .collect();
+                             (rng.range(0, max_reg0 as i64) as u64, chunks)
+                         },
+                         |(r, chunks): &Chunks| {
+                             let mut smaller: Vec<Chunks> = shrink_u64(*r).into_iter()
+                                 .map(|s| (s, chunks.clone()))
+                                 .collect();
+                             smaller.extend(shrink_vec(chunks, |&c| shrink_u64(c)).into_iter()
+                                 .map(|c| (*r, c)));
+                             smaller
+                         },
+                         |(r, chunks)| trace(start(*r), chunks, |cpu, l| p.run_with_cpu(cpu, l)),
+                         |(r, chunks)| trace(start(*r), chunks, |cpu, l| fast.run_with_cpu(cpu, l)));
+
+    Ok(m)
+}
@@ -0,0 +1,118 @@
+use device::Cpu;
+use device::Opcode;
+use device::Reg;
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use Result;
+
+///Result of `addr`, `addi`, `mulr` and `muli` if it does not fit in 64 bits
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Overflow {
+    ///Faults the program
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    Overflow(Opcode),
+    ///Register the cpu does not have
+    InvalidRegister(Reg),
+    StepLimit(u64)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Halted,
+    ///The instruction at `ip` faulted, or was next when the limit was hit
+    Fault { ip: usize, fault: Fault }
+}
+
+///How a run ended and how many instructions it executed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub reason: Reason,
+    pub steps: u64
+}
+
+impl Overflow {
+    pub fn add(self, a: u64, b: u64) -> Option<u64> {
+        match self {
+            Overflow::Checked => a.checked_add(b),
+            Overflow::Wrapping => Some(a.wrapping_add(b)),
+            Overflow::Saturating => Some(a.saturating_add(b))
+        }
+    }
+
+    pub fn mul(self, a: u64, b: u64) -> Option<u64> {
+        match self {
+            Overflow::Checked => a.checked_mul(b),
+            Overflow::Wrapping => Some(a.wrapping_mul(b)),
+            Overflow::Saturating => Some(a.saturating_mul(b))
+        }
+    }
+}
+
+impl Outcome {
+    pub fn halted(&self) -> bool {
+        self.reason == Reason::Halted
+    }
+
+    ///Step count of a halted run, the fault as error otherwise
+    pub fn check(self) -> Result<u64> {
+        match self.reason {
+            Reason::Halted => Ok(self.steps),
+            Reason::Fault { .. } => Err(self.to_string().into())
+        }
+    }
+}
+
+///Executes `step` until it reports a halt, faults or `limit` steps were executed
+pub fn run_limited<F>(cpu: &mut Cpu, limit: Option<u64>, mut step: F) -> Outcome
+    where F: FnMut(&mut Cpu) -> std::result::Result<bool, Fault> {
+    let mut steps = 0;
+
+    let fault = loop {
+        if let Some(l) = limit.filter(|&l| steps >= l) {
+            break Fault::StepLimit(l);
+        }
+
+        let ip = cpu.ip();
+        match step(cpu) {
+            Ok(true) => steps += 1,
+            Ok(false) => return Outcome { reason: Reason::Halted, steps },
+            Err(fault) => return Outcome { reason: Reason::Fault { ip, fault }, steps }
+        }
+    };
+
+    Outcome {
+        reason: Reason::Fault { ip: cpu.ip(), fault },
+        steps
+    }
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Fault::Overflow(op) => write!(f, "arithmetic overflow in {}", op),
+            Fault::InvalidRegister(r) => write!(f, "invalid register {}", r.index()),
+            Fault::StepLimit(l) => write!(f, "step limit of {} exceeded", l)
+        }
+    }
+}
+
+impl Error for Fault {}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self.reason {
+            Reason::Halted => write!(f, "halted after {} steps", self.steps),
+            Reason::Fault { ip, fault } => {
+                write!(f, "{} at instruction {} after {} steps", fault, ip, self.steps)
+            }
+        }
+    }
+}
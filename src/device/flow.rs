@@ -0,0 +1,277 @@
+use device::Opcode;
+use device::Program;
+use device::Reg;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Reg(Reg),
+    Imm(u64)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Mul,
+    And,
+    Or,
+    Gt,
+    Eq
+}
+
+///Value an instruction writes to its target register
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
+    Value(Operand),
+    Bin(BinOp, Operand, Operand)
+}
+
+///How control leaves an instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Exit {
+    Next,
+    ///Constant jump, targets outside the program halt
+    Jump(usize),
+    ///`ip += cond` where cond is the 0/1 result of the previous comparison:
+    ///continues at i + 2 if set, at i + 1 otherwise
+    Skip(Reg),
+    ///Jump to the value of the expression + 1
+    Computed(Expr)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    ///Exclusive
+    pub end: usize,
+    pub exit: Exit
+}
+
+impl Operand {
+    pub fn reg(self) -> Option<Reg> {
+        match self {
+            Operand::Reg(r) => Some(r),
+            Operand::Imm(_) => None
+        }
+    }
+
+    fn substitute(self, r: Reg, val: u64) -> Operand {
+        match self {
+            Operand::Reg(o) if o == r => Operand::Imm(val),
+            o => o
+        }
+    }
+
+    fn eval<F: Fn(Reg) -> u64>(self, regs: &F) -> u64 {
+        match self {
+            Operand::Reg(r) => regs(r),
+            Operand::Imm(v) => v
+        }
+    }
+}
+
+impl BinOp {
+    pub fn is_comparison(self) -> bool {
+        matches!(self, BinOp::Gt | BinOp::Eq)
+    }
+
+    pub fn apply(self, a: u64, b: u64) -> u64 {
+        match self {
+            BinOp::Add => a.wrapping_add(b),
+            BinOp::Mul => a.wrapping_mul(b),
+            BinOp::And => a & b,
+            BinOp::Or => a | b,
+            BinOp::Gt => (a > b) as u64,
+            BinOp::Eq => (a == b) as u64
+        }
+    }
+}
+
+impl Expr {
+    pub fn operands(&self) -> Vec<Operand> {
+        match *self {
+            Expr::Value(a) => vec![a],
+            Expr::Bin(_, a, b) => vec![a, b]
+        }
+    }
+
+    pub fn reads(&self, r: Reg) -> bool {
+        self.operands().iter().any(|o| o.reg() == Some(r))
+    }
+
+    pub fn substitute(&self, r: Reg, val: u64) -> Expr {
+        match *self {
+            Expr::Value(a) => Expr::Value(a.substitute(r, val)),
+            Expr::Bin(op, a, b) => Expr::Bin(op, a.substitute(r, val), b.substitute(r, val))
+        }
+    }
+
+    pub fn constant(&self) -> Option<u64> {
+        if self.operands().iter().all(|o| o.reg().is_none()) {
+            Some(self.eval(&|_| 0))
+        } else {
+            None
+        }
+    }
+
+    pub fn eval<F: Fn(Reg) -> u64>(&self, regs: &F) -> u64 {
+        match *self {
+            Expr::Value(a) => a.eval(regs),
+            Expr::Bin(op, a, b) => op.apply(a.eval(regs), b.eval(regs))
+        }
+    }
+}
+
+impl Opcode {
+    pub fn expr(&self) -> Expr {
+        use self::Opcode::*;
+        use self::Operand::Imm;
+        use self::Operand::Reg as R;
+
+        match *self {
+            Addr { a, b, .. } => Expr::Bin(BinOp::Add, R(a), R(b)),
+            Addi { a, b, .. } => Expr::Bin(BinOp::Add, R(a), Imm(b)),
+            Mulr { a, b, .. } => Expr::Bin(BinOp::Mul, R(a), R(b)),
+            Muli { a, b, .. } => Expr::Bin(BinOp::Mul, R(a), Imm(b)),
+            Banr { a, b, .. } => Expr::Bin(BinOp::And, R(a), R(b)),
+            Bani { a, b, .. } => Expr::Bin(BinOp::And, R(a), Imm(b)),
+            Borr { a, b, .. } => Expr::Bin(BinOp::Or, R(a), R(b)),
+            Bori { a, b, .. } => Expr::Bin(BinOp::Or, R(a), Imm(b)),
+            Setr { a, .. } => Expr::Value(R(a)),
+            Seti { a, .. } => Expr::Value(Imm(a)),
+            Gtir { a, b, .. } => Expr::Bin(BinOp::Gt, Imm(a), R(b)),
+            Gtri { a, b, .. } => Expr::Bin(BinOp::Gt, R(a), Imm(b)),
+            Gtrr { a, b, .. } => Expr::Bin(BinOp::Gt, R(a), R(b)),
+            Eqir { a, b, .. } => Expr::Bin(BinOp::Eq, Imm(a), R(b)),
+            Eqri { a, b, .. } => Expr::Bin(BinOp::Eq, R(a), Imm(b)),
+            Eqrr { a, b, .. } => Expr::Bin(BinOp::Eq, R(a), R(b)),
+        }
+    }
+}
+
+fn writes_comparison(op: &Opcode, r: Reg) -> bool {
+    match op.expr() {
+        Expr::Bin(bin, _, _) => op.target() == r && bin.is_comparison(),
+        _ => false
+    }
+}
+
+///Expression the instruction at `i` writes, with reads of the ip register
+///replaced by its known value
+pub fn resolved_expr(p: &Program, i: usize) -> Expr {
+    let e = p.ops[i].expr();
+    match p.ip_reg {
+        Some(ip) => e.substitute(ip, i as u64),
+        None => e
+    }
+}
+
+fn constant_exit(p: &Program, i: usize) -> Option<Exit> {
+    let ip = match p.ip_reg {
+        Some(ip) => ip,
+        None => return Some(Exit::Next)
+    };
+    if p.ops[i].target() != ip {
+        return Some(Exit::Next);
+    }
+
+    resolved_expr(p, i).constant()
+        .map(|v| Exit::Jump((v as usize).saturating_add(1)))
+}
+
+///Classifies how control leaves every instruction of the program
+pub fn exits(p: &Program) -> Vec<Exit> {
+    let n = p.ops.len();
+    let mut exits: Vec<Option<Exit>> = (0..n)
+        .map(|i| constant_exit(p, i))
+        .collect();
+
+    let targets: BTreeSet<usize> = exits.iter()
+        .filter_map(|e| match e {
+            Some(Exit::Jump(t)) => Some(*t),
+            _ => None
+        })
+        .collect();
+
+    for (i, exit) in exits.iter_mut().enumerate() {
+        if exit.is_some() {
+            continue;
+        }
+
+        let ip = p.ip_reg.unwrap();
+        let e = resolved_expr(p, i);
+
+        //`addr x ip ip` right after `x = a > b` skips the next instruction if x is set
+        let skip = match e {
+            Expr::Bin(BinOp::Add, Operand::Reg(x), Operand::Imm(v)) |
+            Expr::Bin(BinOp::Add, Operand::Imm(v), Operand::Reg(x)) if v == i as u64 && x != ip => {
+                let is_bool = i > 0
+                    && writes_comparison(&p.ops[i - 1], x)
+                    && !targets.contains(&i);
+
+                if is_bool { Some(x) } else { None }
+            }
+            _ => None
+        };
+
+        *exit = Some(match skip {
+            Some(x) => Exit::Skip(x),
+            None => Exit::Computed(e)
+        });
+    }
+
+    exits.into_iter()
+        .map(|e| e.unwrap())
+        .collect()
+}
+
+///Splits the program into basic blocks, ordered by address
+pub fn blocks(p: &Program) -> Vec<Block> {
+    let n = p.ops.len();
+    let exits = exits(p);
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+
+    for (i, e) in exits.iter().enumerate() {
+        match *e {
+            Exit::Next => continue,
+            Exit::Jump(t) => {
+                leaders.insert(t);
+            }
+            Exit::Skip(_) => {
+                leaders.insert(i + 2);
+            }
+            Exit::Computed(_) => {}
+        }
+        leaders.insert(i + 1);
+    }
+
+    let leaders: Vec<usize> = leaders.into_iter()
+        .filter(|&l| l < n)
+        .collect();
+
+    leaders.iter()
+        .enumerate()
+        .map(|(k, &start)| {
+            let end = leaders.get(k + 1).cloned().unwrap_or(n);
+            Block {
+                start,
+                end,
+                exit: exits[end - 1]
+            }
+        })
+        .collect()
+}
+
+impl Block {
+    ///Addresses control can continue at, None for computed jumps.
+    ///Addresses outside the program mean the program halts.
+    pub fn successors(&self) -> Option<Vec<usize>> {
+        match self.exit {
+            Exit::Next => Some(vec![self.end]),
+            Exit::Jump(t) => Some(vec![t]),
+            Exit::Skip(_) => Some(vec![self.end, self.end + 1]),
+            Exit::Computed(_) => None
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use device::fault::Fault;
+use device::Cpu;
+use device::Program;
+use device::Reg;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use util::file::load;
+use Result;
+
+///Undo entries kept by default, older ones are dropped
+pub const DEFAULT_DEPTH: usize = 1_000_000;
+
+///Cpu state after `steps` instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub cpu: Cpu,
+    pub steps: u64
+}
+
+///Registers overwritten by one instruction, with their old values
+#[derive(Debug, Copy, Clone)]
+struct Undo {
+    ip: usize,
+    ip_reg: Option<(Reg, u64)>,
+    target: Option<(Reg, u64)>
+}
+
+///Executes a program and logs the register writes so it can step backwards
+pub struct History<'a> {
+    program: &'a Program,
+    cpu: Cpu,
+    steps: u64,
+    log: VecDeque<Undo>,
+    depth: usize
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &str) -> Result<()> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Snapshot> {
+        load(path)?.parse()
+    }
+}
+
+impl Undo {
+    fn apply(&self, cpu: &mut Cpu) {
+        if let Some((r, v)) = self.target {
+            cpu.set_reg(r, v);
+        }
+        if let Some((r, v)) = self.ip_reg {
+            cpu.set_reg(r, v);
+        }
+        cpu.set_ip(self.ip);
+    }
+}
+
+impl<'a> History<'a> {
+    pub fn new(program: &'a Program, cpu: Cpu) -> History<'a> {
+        History::with_depth(program, cpu, DEFAULT_DEPTH)
+    }
+
+    ///Keeps at most `depth` instructions to step back
+    pub fn with_depth(program: &'a Program, cpu: Cpu, depth: usize) -> History<'a> {
+        History {
+            program,
+            cpu,
+            steps: 0,
+            log: VecDeque::new(),
+            depth
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    ///Instructions that can currently be undone
+    pub fn undo_depth(&self) -> usize {
+        self.log.len()
+    }
+
+    ///Changes a register outside of the program, this clears the undo log
+    pub fn set_reg(&mut self, r: Reg, v: u64) {
+        self.log.clear();
+        self.cpu.set_reg(r, v);
+    }
+
+    ///Moves the ip, this clears the undo log
+    pub fn set_ip(&mut self, ix: usize) {
+        self.log.clear();
+        self.cpu.set_ip(ix);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            steps: self.steps
+        }
+    }
+
+    ///Continues from the snapshot, the undo log starts empty
+    pub fn restore(&mut self, s: &Snapshot) {
+        self.log.clear();
+        self.cpu = s.cpu.clone();
+        self.steps = s.steps;
+    }
+
+    ///Executes a single instruction, returns false if the program halted.
+    ///A faulting instruction leaves the cpu unchanged.
+    pub fn step(&mut self) -> std::result::Result<bool, Fault> {
+        let ix = self.cpu.ip();
+        let op = match self.program.ops.get(ix) {
+            Some(op) => op,
+            None => return Ok(false)
+        };
+
+        let old = |r: Reg| self.cpu.registers().get(r.index()).map(|&v| (r, v));
+        let undo = Undo {
+            ip: ix,
+            ip_reg: self.program.ip_reg.and_then(old),
+            target: old(op.target())
+        };
+
+        if let Err(fault) = self.program.run_once(&mut self.cpu) {
+            undo.apply(&mut self.cpu);
+            return Err(fault);
+        }
+
+        if self.log.len() == self.depth {
+            self.log.pop_front();
+        }
+        if self.depth > 0 {
+            self.log.push_back(undo);
+        }
+        self.steps += 1;
+        Ok(true)
+    }
+
+    ///Undoes the last instruction, returns false if the log is empty
+    pub fn back(&mut self) -> bool {
+        match self.log.pop_back() {
+            Some(undo) => {
+                undo.apply(&mut self.cpu);
+                self.steps -= 1;
+                true
+            }
+            None => false
+        }
+    }
+
+    ///Moves forward or backward to the state after `step` instructions.
+    ///Returns false if the program halts earlier or the log does not reach back far enough.
+    pub fn seek(&mut self, step: u64) -> std::result::Result<bool, Fault> {
+        while self.steps < step {
+            if !self.step()? {
+                return Ok(false);
+            }
+        }
+
+        while self.steps > step {
+            if !self.back() {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    ///First step up to `hi` at which `pred` holds, for predicates that keep
+    ///holding once they do, like a counter passing a value. Stays at that step,
+    ///or at the last reachable one if there is none.
+    pub fn bisect<F: FnMut(&Cpu) -> bool>(&mut self, hi: u64, mut pred: F) -> Result<Option<u64>> {
+        let mut lo = self.steps;
+        if pred(&self.cpu) {
+            return Ok(Some(lo));
+        }
+
+        self.seek(hi)?;
+        let mut hi = self.steps;
+        if !pred(&self.cpu) {
+            return Ok(None);
+        }
+
+        //pred is false at lo and true at hi
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if !self.seek(mid)? {
+                return Err(format!("Undo log does not reach back to step {}", mid).into());
+            }
+            if pred(&self.cpu) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+
+        self.seek(hi)?;
+        Ok(Some(hi))
+    }
+
+    ///First step at which `reg` holds `value`, searched from as far back as
+    ///the undo log reaches up to `limit` steps past the current one.
+    ///Stays at that step, or at the end of the search if there is none.
+    pub fn first_value(&mut self, reg: Reg, value: u64, limit: u64) -> Result<Option<u64>> {
+        let end = self.steps.saturating_add(limit);
+        while self.back() {}
+
+        loop {
+            if self.cpu.get_reg(reg) == value {
+                return Ok(Some(self.steps));
+            }
+
+            if self.steps >= end || !self.step()? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+///`steps`, `ip` and `registers` lines
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let regs: Vec<String> = self.cpu.registers().iter()
+            .map(|v| v.to_string())
+            .collect();
+
+        writeln!(f, "steps {}", self.steps)?;
+        writeln!(f, "ip {}", self.cpu.ip())?;
+        writeln!(f, "registers {}", regs.join(" "))
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (mut steps, mut ip, mut regs) = (None, None, None);
+
+        for l in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let mut parts = l.split_whitespace();
+            match parts.next() {
+                Some("steps") => steps = Some(parts.next().ok_or("Missing step count")?.parse()?),
+                Some("ip") => ip = Some(parts.next().ok_or("Missing ip")?.parse()?),
+                Some("registers") => {
+                    regs = Some(parts.map(|v| v.parse()).collect::<std::result::Result<Vec<u64>, _>>()?)
+                }
+                _ => return Err(format!("Invalid snapshot line {}", l).into())
+            }
+        }
+
+        let mut cpu = Cpu::from_registers(&regs.ok_or("Snapshot without registers")?);
+        cpu.set_ip(ip.ok_or("Snapshot without ip")?);
+
+        Ok(Snapshot {
+            cpu,
+            steps: steps.unwrap_or(0)
+        })
+    }
+}
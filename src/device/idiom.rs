@@ -0,0 +1,251 @@
+use device::flow::exits;
+use device::flow::Exit;
+use device::fault::run_limited;
+use device::fault::Fault;
+use device::fault::Outcome;
+use device::Cpu;
+use device::Opcode;
+use device::Program;
+use device::Reg;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+///Loop with a known closed form
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Idiom {
+    ///`sum += i` for every `i` in `1..=n` that has a `j` in `1..=n` with `i * j == n`
+    DivisorSum { n: Reg, sum: Reg, i: Reg, j: Reg, tmp: Reg },
+    ///Smallest `q` with `(q + 1) * k > d`, i.e. `q = d / k`
+    Divide { d: Reg, k: u64, q: Reg, tmp: Reg }
+}
+
+///Idiom occupying the instructions `start..end`, continuing at `exit`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub exit: usize,
+    pub idiom: Idiom
+}
+
+pub struct Optimized<'a> {
+    program: &'a Program,
+    idioms: Vec<Option<Match>>
+}
+
+fn distinct(regs: &[Reg]) -> bool {
+    regs.iter()
+        .enumerate()
+        .all(|(k, r)| !regs[k + 1..].contains(r))
+}
+
+///The other operand of a commutative register pair containing `r`
+fn other(a: Reg, b: Reg, r: Reg) -> Option<Reg> {
+    if a == r {
+        Some(b)
+    } else if b == r {
+        Some(a)
+    } else {
+        None
+    }
+}
+
+fn divisor_sum(p: &Program, e: &[Exit], s: usize, ip: Reg) -> Option<Match> {
+    use self::Opcode::*;
+    let op = |k: usize| p.ops.get(s + k).cloned();
+    let exit = |k: usize| e.get(s + k).cloned();
+
+    let i = match op(0)? { Seti { a: 1, c, .. } => c, _ => return None };
+    let j = match op(1)? { Seti { a: 1, c, .. } => c, _ => return None };
+    let tmp = match op(2)? {
+        Mulr { a, b, c } if other(a, b, i) == Some(j) => c,
+        _ => return None
+    };
+    let n = match op(3)? {
+        Eqrr { a, b, c } if c == tmp => other(a, b, tmp)?,
+        _ => return None
+    };
+    let sum = match op(6)? {
+        Addr { a, b, c } => {
+            if other(a, b, c)? != i { return None; }
+            c
+        }
+        _ => return None
+    };
+
+    let shape = exit(4)? == Exit::Skip(tmp)
+        && exit(5)? == Exit::Jump(s + 7)
+        && op(7)? == Addi { a: j, b: 1, c: j }
+        && op(8)? == Gtrr { a: j, b: n, c: tmp }
+        && exit(9)? == Exit::Skip(tmp)
+        && exit(10)? == Exit::Jump(s + 2)
+        && op(11)? == Addi { a: i, b: 1, c: i }
+        && op(12)? == Gtrr { a: i, b: n, c: tmp }
+        && exit(13)? == Exit::Skip(tmp)
+        && exit(14)? == Exit::Jump(s + 1);
+
+    if !shape || !distinct(&[n, sum, i, j, tmp, ip]) {
+        return None;
+    }
+
+    Some(Match {
+        start: s,
+        end: s + 15,
+        exit: s + 15,
+        idiom: Idiom::DivisorSum { n, sum, i, j, tmp }
+    })
+}
+
+fn divide(p: &Program, e: &[Exit], s: usize, ip: Reg) -> Option<Match> {
+    use self::Opcode::*;
+    let op = |k: usize| p.ops.get(s + k).cloned();
+
+    let q = match op(0)? { Seti { a: 0, c, .. } => c, _ => return None };
+    let tmp = match op(1)? { Addi { a, b: 1, c } if a == q => c, _ => return None };
+    let k = match op(2)? { Muli { a, b, c } if a == tmp && c == tmp && b > 0 => b, _ => return None };
+    let d = match op(3)? { Gtrr { a, b, c } if a == tmp && c == tmp => b, _ => return None };
+    let exit = match e.get(s + 6)? { Exit::Jump(t) => *t, _ => return None };
+
+    let shape = e.get(s + 4)? == &Exit::Skip(tmp)
+        && e.get(s + 5)? == &Exit::Jump(s + 7)
+        && op(7)? == Addi { a: q, b: 1, c: q }
+        && e.get(s + 8)? == &Exit::Jump(s + 1);
+
+    if !shape || !distinct(&[d, q, tmp, ip]) {
+        return None;
+    }
+
+    Some(Match {
+        start: s,
+        end: s + 9,
+        exit,
+        idiom: Idiom::Divide { d, k, q, tmp }
+    })
+}
+
+///Finds all idioms of a program with an ip register
+pub fn recognise(p: &Program) -> Vec<Match> {
+    let ip = match p.ip_reg {
+        Some(ip) => ip,
+        None => return Vec::new()
+    };
+    let e = exits(p);
+
+    (0..p.ops.len())
+        .filter_map(|s| divisor_sum(p, &e, s, ip).or_else(|| divide(p, &e, s, ip)))
+        .collect()
+}
+
+fn sum_of_divisors(n: u64) -> u64 {
+    let mut sum = 0;
+    let mut i = 1;
+
+    while i * i <= n {
+        if n % i == 0 {
+            sum += i;
+            if i != n / i {
+                sum += n / i;
+            }
+        }
+        i += 1;
+    }
+
+    sum
+}
+
+impl Match {
+    ///Applies the effect of the loop to the registers. Returns false and
+    ///leaves `cpu` untouched if the closed form does not cover its values.
+    pub fn apply(&self, cpu: &mut Cpu, ip: Reg) -> bool {
+        match self.idiom {
+            Idiom::DivisorSum { n, sum, i, j, tmp } => {
+                let nv = cpu.get_reg(n);
+                if nv == 0 || nv > u64::from(u32::MAX) {
+                    return false;
+                }
+
+                let s = cpu.get_reg(sum).wrapping_add(sum_of_divisors(nv));
+                cpu.set_reg(sum, s);
+                cpu.set_reg(i, nv + 1);
+                cpu.set_reg(j, nv + 1);
+                cpu.set_reg(tmp, 1);
+            }
+            Idiom::Divide { d, k, q, tmp } => {
+                let qv = cpu.get_reg(d) / k;
+                if (qv + 1).checked_mul(k).is_none() {
+                    return false;
+                }
+
+                cpu.set_reg(q, qv);
+                cpu.set_reg(tmp, 1);
+            }
+        }
+
+        cpu.set_reg(ip, (self.exit - 1) as u64);
+        cpu.set_ip(self.exit);
+        true
+    }
+}
+
+impl<'a> Optimized<'a> {
+    pub fn new(program: &'a Program) -> Optimized<'a> {
+        let mut idioms = vec![None; program.ops.len()];
+        for m in recognise(program) {
+            idioms[m.start] = Some(m);
+        }
+
+        Optimized {
+            program,
+            idioms
+        }
+    }
+
+    pub fn matches(&self) -> Vec<Match> {
+        self.idioms.iter()
+            .filter_map(|m| *m)
+            .collect()
+    }
+
+    ///Executes an idiom starting at the ip or a single instruction,
+    ///returns false if the program halted
+    pub fn run_once(&self, cpu: &mut Cpu) -> std::result::Result<bool, Fault> {
+        if let (Some(Some(m)), Some(ip)) = (self.idioms.get(cpu.ip()), self.program.ip_reg) {
+            if m.apply(cpu, ip) {
+                return Ok(true);
+            }
+        }
+
+        self.program.run_once(cpu)
+    }
+
+    ///Runs until the program halts, faults or executed `limit` steps,
+    ///an idiom counts as a single step
+    pub fn run_with_cpu(&self, cpu: &mut Cpu, limit: Option<u64>) -> Outcome {
+        run_limited(cpu, limit, |cpu| self.run_once(cpu))
+    }
+
+    pub fn run(&self, limit: Option<u64>) -> (Cpu, Outcome) {
+        let mut cpu = self.program.cpu();
+        let outcome = self.run_with_cpu(&mut cpu, limit);
+        (cpu, outcome)
+    }
+}
+
+impl Display for Idiom {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match *self {
+            Idiom::DivisorSum { n, sum, .. } => {
+                write!(f, "r{} += sum of divisors of r{}", sum.index(), n.index())
+            }
+            Idiom::Divide { d, k, q, .. } => {
+                write!(f, "r{} = r{} / {}", q.index(), d.index(), k)
+            }
+        }
+    }
+}
+
+impl Display for Match {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}..{}: {}, continue at {}", self.start, self.end, self.idiom, self.exit)
+    }
+}
@@ -0,0 +1,90 @@
+pub mod asm;
+pub mod cfg;
+pub mod debugger;
+pub mod decompile;
+pub mod deduce;
+pub mod fast;
+pub mod fault;
+pub mod idiom;
+pub mod flow;
+pub mod history;
+pub mod opcode;
+pub mod probe;
+pub mod profile;
+pub mod program;
+pub mod symbolic;
+
+pub use self::debugger::Debugger;
+pub use self::opcode::Opcode;
+pub use self::program::Program;
+
+use Result;
+
+///Raw numeric instruction as used by the day 16 samples: `[op, a, b, c]`
+pub type Instruction = [u64; 4];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg(usize);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cpu {
+    register: Vec<u64>,
+    ip: usize
+}
+
+impl Reg {
+    ///Register `ix` of a cpu with `regs` registers
+    pub fn new(ix: usize, regs: usize) -> Result<Reg> {
+        if ix < regs {
+            Ok(Reg(ix))
+        } else {
+            Err(format!("Invalid register index {}", ix).into())
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl Cpu {
+    pub fn new(regs: usize) -> Cpu {
+        Cpu {
+            register: vec![0; regs],
+            ip: 0
+        }
+    }
+
+    pub fn from_registers(regs: &[u64]) -> Cpu {
+        Cpu {
+            register: regs.to_vec(),
+            ip: 0
+        }
+    }
+
+    ///Index of the next instruction. If the program binds the ip to a
+    ///register, it is written there before and read back after each instruction.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+    }
+
+    pub fn registers(&self) -> &[u64] {
+        &self.register
+    }
+
+    pub fn reg_count(&self) -> usize {
+        self.register.len()
+    }
+
+    pub fn get_reg(&self, r: Reg) -> u64 {
+        self.register[r.index()]
+    }
+
+    pub fn set_reg(&mut self, r: Reg, val: u64) {
+        self.register[r.index()] = val
+    }
+}
@@ -0,0 +1,257 @@
+use device::fault::Fault;
+use device::fault::Overflow;
+use device::Cpu;
+use device::Reg;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use Result;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Addr { a: Reg, b: Reg, c: Reg },
+    Addi { a: Reg, b: u64, c: Reg },
+
+    Mulr { a: Reg, b: Reg, c: Reg },
+    Muli { a: Reg, b: u64, c: Reg },
+
+    Banr { a: Reg, b: Reg, c: Reg },
+    Bani { a: Reg, b: u64, c: Reg },
+
+    Borr { a: Reg, b: Reg, c: Reg },
+    Bori { a: Reg, b: u64, c: Reg },
+
+    ///`b` is ignored, it is kept to print the instruction as it was read
+    Setr { a: Reg, b: u64, c: Reg },
+    Seti { a: u64, b: u64, c: Reg },
+
+    Gtir { a: u64, b: Reg, c: Reg },
+    Gtri { a: Reg, b: u64, c: Reg },
+    Gtrr { a: Reg, b: Reg, c: Reg },
+
+    Eqir { a: u64, b: Reg, c: Reg },
+    Eqri { a: Reg, b: u64, c: Reg },
+    Eqrr { a: Reg, b: Reg, c: Reg },
+}
+
+pub const MNEMONICS: [&str; 16] = [
+    "addr", "addi",
+    "mulr", "muli",
+    "banr", "bani",
+    "borr", "bori",
+    "setr", "seti",
+    "gtir", "gtri", "gtrr",
+    "eqir", "eqri", "eqrr",
+];
+
+fn get(cpu: &Cpu, r: Reg) -> std::result::Result<u64, Fault> {
+    cpu.registers()
+        .get(r.index())
+        .cloned()
+        .ok_or(Fault::InvalidRegister(r))
+}
+
+fn set(cpu: &mut Cpu, r: Reg, v: u64) -> std::result::Result<(), Fault> {
+    if r.index() >= cpu.reg_count() {
+        return Err(Fault::InvalidRegister(r));
+    }
+
+    cpu.set_reg(r, v);
+    Ok(())
+}
+
+impl Opcode {
+    ///Executes the instruction, `overflow` decides the result of additions
+    ///and multiplications that do not fit in 64 bits
+    pub fn exec(&self, cpu: &mut Cpu, overflow: Overflow) -> std::result::Result<(), Fault> {
+        use self::Opcode::*;
+        match *self {
+            Addr { a, b, c } => {
+                let a = get(cpu, a)?;
+                let b = get(cpu, b)?;
+                set(cpu, c, overflow.add(a, b).ok_or(Fault::Overflow(*self))?)?;
+            }
+            Addi { a, b, c } => {
+                let a = get(cpu, a)?;
+                set(cpu, c, overflow.add(a, b).ok_or(Fault::Overflow(*self))?)?;
+            }
+
+            Mulr { a, b, c } => {
+                let a = get(cpu, a)?;
+                let b = get(cpu, b)?;
+                set(cpu, c, overflow.mul(a, b).ok_or(Fault::Overflow(*self))?)?;
+            }
+            Muli { a, b, c } => {
+                let a = get(cpu, a)?;
+                set(cpu, c, overflow.mul(a, b).ok_or(Fault::Overflow(*self))?)?;
+            }
+
+            Borr { a, b, c } => {
+                let a = get(cpu, a)?;
+                let b = get(cpu, b)?;
+                set(cpu, c, a | b)?;
+            }
+            Bori { a, b, c } => {
+                let a = get(cpu, a)?;
+                set(cpu, c, a | b)?;
+            }
+
+            Banr { a, b, c } => {
+                let a = get(cpu, a)?;
+                let b = get(cpu, b)?;
+                set(cpu, c, a & b)?;
+            }
+            Bani { a, b, c } => {
+                let a = get(cpu, a)?;
+                set(cpu, c, a & b)?;
+            }
+
+            Setr { a, c, .. } => {
+                let a = get(cpu, a)?;
+                set(cpu, c, a)?;
+            }
+            Seti { a, c, .. } => {
+                set(cpu, c, a)?;
+            }
+
+            Gtir { a, b, c } => {
+                let b = get(cpu, b)?;
+                set(cpu, c, (a > b) as u64)?;
+            }
+            Gtri { a, b, c } => {
+                let a = get(cpu, a)?;
+                set(cpu, c, (a > b) as u64)?;
+            }
+            Gtrr { a, b, c } => {
+                let a = get(cpu, a)?;
+                let b = get(cpu, b)?;
+                set(cpu, c, (a > b) as u64)?;
+            }
+
+            Eqir { a, b, c } => {
+                let b = get(cpu, b)?;
+                set(cpu, c, (a == b) as u64)?;
+            }
+            Eqri { a, b, c } => {
+                let a = get(cpu, a)?;
+                set(cpu, c, (a == b) as u64)?;
+            }
+            Eqrr { a, b, c } => {
+                let a = get(cpu, a)?;
+                let b = get(cpu, b)?;
+                set(cpu, c, (a == b) as u64)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    ///Builds the opcode with reference index `op` (0 = addr ... 15 = eqrr)
+    pub fn from_index(op: usize, a: u64, b: u64, c: u64, regs: usize) -> Result<Opcode> {
+        use self::Opcode::*;
+        let reg = |i| Reg::new(i as usize, regs);
+
+        let op = match op {
+            0 => Addr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
+            1 => Addi { a: reg(a)?, b, c: reg(c)? },
+
+            2 => Mulr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
+            3 => Muli { a: reg(a)?, b, c: reg(c)? },
+
+            4 => Banr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
+            5 => Bani { a: reg(a)?, b, c: reg(c)? },
+
+            6 => Borr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
+            7 => Bori { a: reg(a)?, b, c: reg(c)? },
+
+            8 => Setr { a: reg(a)?, b, c: reg(c)? },
+            9 => Seti { a, b, c: reg(c)? },
+
+            10 => Gtir { a, b: reg(b)?, c: reg(c)? },
+            11 => Gtri { a: reg(a)?, b, c: reg(c)? },
+            12 => Gtrr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
+
+            13 => Eqir { a, b: reg(b)?, c: reg(c)? },
+            14 => Eqri { a: reg(a)?, b, c: reg(c)? },
+            15 => Eqrr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
+            _ => return Err("Invalid opcode".into())
+        };
+
+        Ok(op)
+    }
+
+    pub fn from_mnemonic(instr: &str, a: u64, b: u64, c: u64, regs: usize) -> Result<Opcode> {
+        let op = MNEMONICS.iter()
+            .position(|&m| m == instr)
+            .ok_or_else(|| format!("Invalid opcode {}", instr))?;
+
+        Opcode::from_index(op, a, b, c, regs)
+    }
+
+    pub fn index(&self) -> usize {
+        use self::Opcode::*;
+        match self {
+            Addr { .. } => 0,
+            Addi { .. } => 1,
+            Mulr { .. } => 2,
+            Muli { .. } => 3,
+            Banr { .. } => 4,
+            Bani { .. } => 5,
+            Borr { .. } => 6,
+            Bori { .. } => 7,
+            Setr { .. } => 8,
+            Seti { .. } => 9,
+            Gtir { .. } => 10,
+            Gtri { .. } => 11,
+            Gtrr { .. } => 12,
+            Eqir { .. } => 13,
+            Eqri { .. } => 14,
+            Eqrr { .. } => 15,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        MNEMONICS[self.index()]
+    }
+
+    ///Raw operands, including the unused b of `setr` and `seti`
+    pub fn operands(&self) -> (u64, u64, u64) {
+        use self::Opcode::*;
+        let r = |r: Reg| r.index() as u64;
+
+        match *self {
+            Addr { a, b, c } | Mulr { a, b, c } | Banr { a, b, c } |
+            Borr { a, b, c } | Gtrr { a, b, c } | Eqrr { a, b, c } => (r(a), r(b), r(c)),
+
+            Addi { a, b, c } | Muli { a, b, c } | Bani { a, b, c } |
+            Bori { a, b, c } | Gtri { a, b, c } | Eqri { a, b, c } => (r(a), b, r(c)),
+
+            Gtir { a, b, c } | Eqir { a, b, c } => (a, r(b), r(c)),
+
+            Setr { a, b, c } => (r(a), b, r(c)),
+            Seti { a, b, c } => (a, b, r(c)),
+        }
+    }
+
+    ///Register written by the instruction
+    pub fn target(&self) -> Reg {
+        use self::Opcode::*;
+        match *self {
+            Addr { c, .. } | Addi { c, .. } | Mulr { c, .. } | Muli { c, .. } |
+            Banr { c, .. } | Bani { c, .. } | Borr { c, .. } | Bori { c, .. } |
+            Setr { c, .. } | Seti { c, .. } |
+            Gtir { c, .. } | Gtri { c, .. } | Gtrr { c, .. } |
+            Eqir { c, .. } | Eqri { c, .. } | Eqrr { c, .. } => c
+        }
+    }
+
+    pub fn count() -> usize {
+        MNEMONICS.len()
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let (a, b, c) = self.operands();
+        write!(f, "{} {} {} {}", self.mnemonic(), a, b, c)
+    }
+}
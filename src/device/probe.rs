@@ -0,0 +1,120 @@
+use device::fast::FastProgram;
+use device::fault::Fault;
+use device::Cpu;
+use device::Program;
+use std::collections::HashMap;
+use std::hash::Hash;
+use Result;
+
+///Runs a program on the fast interpreter and yields the step count and
+///registers every time the instruction `at` is about to execute.
+///It ends when the program halts, faults or reaches the limit.
+pub struct Probe {
+    fast: FastProgram,
+    cpu: Cpu,
+    at: usize,
+    steps: u64,
+    limit: u64,
+    started: bool,
+    fault: Option<Fault>
+}
+
+///First value of a sequence that was seen before
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repeat<T> {
+    pub value: T,
+    ///Indices of the first occurrence and of the repeat, their
+    ///difference is the cycle length if the sequence is periodic
+    pub first: usize,
+    pub index: usize,
+    ///Value before the repeat, the last new one
+    pub last_new: T
+}
+
+impl Probe {
+    pub fn new(p: &Program, cpu: Cpu, at: usize) -> Result<Probe> {
+        Ok(Probe {
+            fast: FastProgram::compile(p)?,
+            cpu,
+            at,
+            steps: 0,
+            limit: u64::MAX,
+            started: false,
+            fault: None
+        })
+    }
+
+    ///Stops after `limit` executed instructions
+    pub fn with_limit(mut self, limit: u64) -> Probe {
+        self.limit = limit;
+        self
+    }
+
+    ///State at the last hit, or where the program halted
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    ///Fault of the instruction at the ip of `cpu()` that ended the probe
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    fn run(&mut self, breakpoint: Option<usize>, limit: u64) {
+        let limit = limit.min(self.limit - self.steps);
+        let (steps, fault) = self.fast.run_until(&mut self.cpu, breakpoint, limit);
+        self.steps += steps;
+        self.fault = fault;
+    }
+}
+
+impl Iterator for Probe {
+    type Item = (u64, Vec<u64>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        //Step off the previous hit
+        if self.started {
+            if self.steps >= self.limit || self.fault.is_some() {
+                return None;
+            }
+            self.run(None, 1);
+        }
+        self.started = true;
+
+        if self.fault.is_none() {
+            self.run(Some(self.at), u64::MAX);
+        }
+        if self.fault.is_some() || self.cpu.ip() != self.at {
+            return None;
+        }
+
+        Some((self.steps, self.cpu.registers().to_vec()))
+    }
+}
+
+///Consumes `iter` until a value repeats
+pub fn first_repeat<T, I>(iter: I) -> Option<Repeat<T>>
+    where T: Hash + Eq + Clone, I: IntoIterator<Item=T> {
+    let mut seen = HashMap::new();
+    let mut last = None;
+
+    for (index, value) in iter.into_iter().enumerate() {
+        if let Some(&first) = seen.get(&value) {
+            return Some(Repeat {
+                value,
+                first,
+                index,
+                last_new: last?
+            });
+        }
+
+        seen.insert(value.clone(), index);
+        last = Some(value);
+    }
+
+    None
+}
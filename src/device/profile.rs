@@ -0,0 +1,150 @@
+use device::flow::exits;
+use device::flow::Exit;
+use device::fault::Fault;
+use device::Cpu;
+use device::Program;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::time::Duration;
+use std::time::Instant;
+
+///Execution counts of a profiling run
+#[derive(Debug, Clone)]
+pub struct Profile {
+    ops: Vec<String>,
+    branches: Vec<bool>,
+    pub hits: Vec<u64>,
+    ///How often a branch did not continue at the next instruction
+    pub taken: Vec<u64>,
+    pub total: u64,
+    pub elapsed: Duration,
+    pub halted: bool,
+    pub fault: Option<Fault>
+}
+
+impl Profile {
+    fn new(p: &Program) -> Profile {
+        let branches = exits(p).iter()
+            .map(|e| *e != Exit::Next)
+            .collect();
+
+        Profile {
+            ops: p.ops.iter().map(|op| op.to_string()).collect(),
+            branches,
+            hits: vec![0; p.ops.len()],
+            taken: vec![0; p.ops.len()],
+            total: 0,
+            elapsed: Duration::default(),
+            halted: false,
+            fault: None
+        }
+    }
+
+    pub fn is_branch(&self, ix: usize) -> bool {
+        self.branches[ix]
+    }
+
+    ///Taken ratio of a branch, None if the instruction is no branch or never ran
+    pub fn taken_ratio(&self, ix: usize) -> Option<f64> {
+        if !self.branches[ix] || self.hits[ix] == 0 {
+            return None;
+        }
+
+        Some(self.taken[ix] as f64 / self.hits[ix] as f64)
+    }
+
+    pub fn cycles_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.total as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    ///Instruction indices ordered by hit count, hottest first
+    pub fn hottest(&self) -> Vec<usize> {
+        let mut ixs: Vec<usize> = (0..self.hits.len()).collect();
+        ixs.sort_by_key(|&ix| std::cmp::Reverse(self.hits[ix]));
+        ixs
+    }
+
+    pub fn coverage(&self) -> usize {
+        self.hits.iter().filter(|&&h| h > 0).count()
+    }
+}
+
+///Runs the program until it halts, faults or `limit` instructions were executed
+pub fn profile(p: &Program, cpu: &mut Cpu, limit: Option<u64>) -> Profile {
+    let mut prof = Profile::new(p);
+    let start = Instant::now();
+
+    loop {
+        if limit.is_some_and(|l| prof.total >= l) {
+            break;
+        }
+
+        let ix = cpu.ip();
+        match p.run_once(cpu) {
+            Ok(true) => {}
+            Ok(false) => {
+                prof.halted = true;
+                break;
+            }
+            Err(fault) => {
+                prof.fault = Some(fault);
+                break;
+            }
+        }
+
+        prof.total += 1;
+        prof.hits[ix] += 1;
+        if cpu.ip() != ix + 1 {
+            prof.taken[ix] += 1;
+        }
+    }
+
+    prof.elapsed = start.elapsed();
+    prof
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let end = match self.fault {
+            Some(fault) => fault.to_string(),
+            None if self.halted => "halted".to_string(),
+            None => "stopped at limit".to_string()
+        };
+
+        writeln!(f, "{} instructions in {:.3}s ({:.0}/s), {}",
+                 self.total,
+                 self.elapsed.as_secs_f64(),
+                 self.cycles_per_second(),
+                 end)?;
+        writeln!(f, "coverage: {}/{} instructions", self.coverage(), self.ops.len())?;
+        writeln!(f, "{:>12} {:>7} {:>7}  {:>4}  instr", "hits", "%", "taken", "ix")?;
+
+        for (ix, op) in self.ops.iter().enumerate() {
+            let pct = if self.total > 0 {
+                100.0 * self.hits[ix] as f64 / self.total as f64
+            } else {
+                0.0
+            };
+
+            let taken = match self.taken_ratio(ix) {
+                Some(r) => format!("{:.1}%", 100.0 * r),
+                None => String::new()
+            };
+
+            let hits = if self.hits[ix] > 0 {
+                self.hits[ix].to_string()
+            } else {
+                "-".to_string()
+            };
+
+            writeln!(f, "{:>12} {:>6.2}% {:>7}  {:4}  {}", hits, pct, taken, ix, op)?;
+        }
+
+        Ok(())
+    }
+}
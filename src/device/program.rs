@@ -0,0 +1,247 @@
+use device::fault::run_limited;
+use device::fault::Fault;
+use device::fault::Outcome;
+use device::fault::Overflow;
+use device::Cpu;
+use device::Instruction;
+use device::Opcode;
+use device::Reg;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub ops: Vec<Opcode>,
+    pub ip_reg: Option<Reg>,
+    regs: usize,
+    overflow: Overflow
+}
+
+///Register count of the `#ip` programs of days 19 and 21
+pub const DEFAULT_REGS: usize = 6;
+
+impl Default for Program {
+    fn default() -> Self {
+        Program::new(Vec::new(), None, DEFAULT_REGS)
+    }
+}
+
+impl Program {
+    pub fn new(ops: Vec<Opcode>, ip_reg: Option<Reg>, regs: usize) -> Program {
+        Program {
+            ops,
+            ip_reg,
+            regs,
+            overflow: Overflow::default()
+        }
+    }
+
+    pub fn with_overflow(mut self, overflow: Overflow) -> Program {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn reg_count(&self) -> usize {
+        self.regs
+    }
+
+    pub fn cpu(&self) -> Cpu {
+        Cpu::new(self.regs)
+    }
+
+    ///Parses the mnemonic format with an optional `#ip N` header line. A
+    ///leading `.regs N` line overrides the register count `regs`.
+    pub fn parse_mnemonic(s: &str, mut regs: usize) -> Result<Program> {
+        let mut ip_reg = None;
+        let mut ops = Vec::new();
+
+        for l in s.lines() {
+            let l = l.trim();
+            if l.is_empty() {
+                continue;
+            }
+
+            if l.starts_with(".regs") {
+                if ip_reg.is_some() || !ops.is_empty() {
+                    return Err(".regs must come first".into());
+                }
+                regs = l.split_whitespace()
+                    .nth(1)
+                    .ok_or("no register count")?
+                    .parse()?;
+                continue;
+            }
+
+            if l.starts_with("#ip") {
+                let n = l.split_whitespace()
+                    .nth(1)
+                    .ok_or("no ip index")?
+                    .parse()?;
+                ip_reg = Some(Reg::new(n, regs)?);
+                continue;
+            }
+
+            let mut parts = l.split_whitespace();
+            let instr = parts.next().ok_or("no instruction")?;
+            let (a, b, c) = parse_operands(&mut parts)?;
+            ops.push(Opcode::from_mnemonic(instr, a, b, c, regs)?);
+        }
+
+        Ok(Program::new(ops, ip_reg, regs))
+    }
+
+    ///Parses the numeric day 16 format. `table` maps the numbers of the
+    ///input to the reference opcode index.
+    pub fn parse_numeric(s: &str, table: &[usize], regs: usize) -> Result<Program> {
+        let instrs = s.lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(parse_instruction)
+            .collect::<Result<Vec<Instruction>>>()?;
+
+        Program::from_instructions(&instrs, table, regs)
+    }
+
+    pub fn from_instructions(instrs: &[Instruction], table: &[usize], regs: usize) -> Result<Program> {
+        let ops = instrs.iter()
+            .map(|i| {
+                let op = *table.get(i[0] as usize)
+                    .ok_or("Opcode number without mapping")?;
+                Opcode::from_index(op, i[1], i[2], i[3], regs)
+            })
+            .collect::<Result<Vec<Opcode>>>()?;
+
+        Ok(Program::new(ops, None, regs))
+    }
+
+    ///Encodes the program in the numeric day 16 format, the inverse of
+    ///`from_instructions` with the same `table`
+    pub fn to_instructions(&self, table: &[usize]) -> Result<Vec<Instruction>> {
+        if self.ip_reg.is_some() {
+            return Err("The numeric format cannot bind the ip register".into());
+        }
+
+        let mut numbers = vec![None; Opcode::count()];
+        for (num, &op) in table.iter().enumerate() {
+            *numbers.get_mut(op).ok_or("Invalid opcode in table")? = Some(num as u64);
+        }
+
+        self.ops.iter()
+            .map(|op| {
+                let num = numbers[op.index()]
+                    .ok_or_else(|| format!("No opcode number for {}", op.mnemonic()))?;
+                let (a, b, c) = op.operands();
+                Ok([num, a, b, c])
+            })
+            .collect()
+    }
+
+    pub fn to_numeric(&self, table: &[usize]) -> Result<String> {
+        let s = self.to_instructions(table)?
+            .iter()
+            .map(|i| format!("{} {} {} {}\n", i[0], i[1], i[2], i[3]))
+            .collect();
+
+        Ok(s)
+    }
+
+    pub fn ip(&self, cpu: &Cpu) -> usize {
+        cpu.ip()
+    }
+
+    ///Executes the instruction at the ip, returns false if the program halted
+    pub fn run_once(&self, cpu: &mut Cpu) -> std::result::Result<bool, Fault> {
+        let ix = cpu.ip();
+
+        match self.ops.get(ix) {
+            Some(instr) => {
+                if let Some(r) = self.ip_reg {
+                    if r.index() >= cpu.reg_count() {
+                        return Err(Fault::InvalidRegister(r));
+                    }
+                    cpu.set_reg(r, ix as u64);
+                }
+
+                instr.exec(cpu, self.overflow)?;
+
+                let next = match self.ip_reg {
+                    Some(r) => (cpu.get_reg(r) as usize).saturating_add(1),
+                    None => ix + 1
+                };
+                cpu.set_ip(next);
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    ///Runs until the program halts, faults or executed `limit` instructions
+    pub fn run_with_cpu(&self, cpu: &mut Cpu, limit: Option<u64>) -> Outcome {
+        run_limited(cpu, limit, |cpu| self.run_once(cpu))
+    }
+
+    pub fn run(&self, limit: Option<u64>) -> (Cpu, Outcome) {
+        let mut cpu = self.cpu();
+        let outcome = self.run_with_cpu(&mut cpu, limit);
+        (cpu, outcome)
+    }
+}
+
+fn parse_operands<'a, I: Iterator<Item=&'a str>>(parts: &mut I) -> Result<(u64, u64, u64)> {
+    let a = parts.next()
+        .ok_or("no a")?
+        .parse()?;
+    let b = parts.next()
+        .ok_or("no b")?
+        .parse()?;
+    let c = parts.next()
+        .ok_or("no c")?
+        .parse()?;
+
+    Ok((a, b, c))
+}
+
+pub fn parse_instruction(s: &str) -> Result<Instruction> {
+    let mut instr = [0; 4];
+    for (i, s) in s.split_whitespace().enumerate() {
+        if i >= 4 {
+            return Err("Invalid amount of numbers in instruction".into());
+        }
+
+        instr[i] = s.parse()?;
+    }
+
+    Ok(instr)
+}
+
+impl FromStr for Program {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Program::parse_mnemonic(s, DEFAULT_REGS)
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.regs != DEFAULT_REGS {
+            writeln!(f, ".regs {}", self.regs)?;
+        }
+
+        if let Some(r) = self.ip_reg {
+            writeln!(f, "#ip {}", r.index())?;
+        }
+
+        for op in self.ops.iter() {
+            writeln!(f, "{}", op)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,376 @@
+use device::flow::resolved_expr;
+use device::flow::BinOp;
+use device::flow::Expr;
+use device::flow::Operand;
+use device::Cpu;
+use device::Program;
+use device::Reg;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use z3::Ast;
+use z3::Config;
+use z3::Context;
+use z3::Solver;
+use Result;
+
+const BITS: u32 = 64;
+///Most targets a single computed jump may fork into
+const MAX_FORKS: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+///`reg cmp rhs`, compared unsigned
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cond {
+    pub reg: Reg,
+    pub cmp: Cmp,
+    pub rhs: Operand
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Goal {
+    Halt,
+    ///About to execute the instruction
+    Reach(usize)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    goal: Goal,
+    max_steps: usize,
+    symbolic: Vec<Reg>,
+    assume: Vec<Cond>,
+    require: Vec<Cond>
+}
+
+///Initial registers that reach the goal after `steps` instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Witness {
+    pub registers: Vec<u64>,
+    pub steps: usize
+}
+
+impl Cond {
+    pub fn new(reg: Reg, cmp: Cmp, rhs: Operand) -> Cond {
+        Cond { reg, cmp, rhs }
+    }
+}
+
+impl Query {
+    pub fn halt(max_steps: usize) -> Query {
+        Query::new(Goal::Halt, max_steps)
+    }
+
+    pub fn reach(target: usize, max_steps: usize) -> Query {
+        Query::new(Goal::Reach(target), max_steps)
+    }
+
+    fn new(goal: Goal, max_steps: usize) -> Query {
+        Query {
+            goal,
+            max_steps,
+            symbolic: Vec::new(),
+            assume: Vec::new(),
+            require: Vec::new()
+        }
+    }
+
+    ///Treats the initial value of the register as unknown
+    pub fn symbolic(mut self, r: Reg) -> Query {
+        self.symbolic.push(r);
+        self
+    }
+
+    ///Constrains the initial registers
+    pub fn assume(mut self, c: Cond) -> Query {
+        self.assume.push(c);
+        self
+    }
+
+    ///Constrains the registers when the goal is reached
+    pub fn require(mut self, c: Cond) -> Query {
+        self.require.push(c);
+        self
+    }
+}
+
+#[derive(Clone)]
+enum Value<'ctx> {
+    Const(u64),
+    Sym(Ast<'ctx>)
+}
+
+#[derive(Clone)]
+struct State<'ctx> {
+    regs: Vec<Value<'ctx>>,
+    ip: usize,
+    steps: usize,
+    path: Vec<Ast<'ctx>>
+}
+
+struct Executor<'ctx> {
+    ctx: &'ctx Context,
+    solver: Solver<'ctx>,
+    zero: Ast<'ctx>,
+    one: Ast<'ctx>,
+    numerals: HashMap<u64, Ast<'ctx>>
+}
+
+impl<'ctx> Executor<'ctx> {
+    fn new(ctx: &'ctx Context) -> Executor<'ctx> {
+        //The bindings have no bitvector numerals, so they are built from 1
+        let x = ctx.fresh_bitvector_const("x", BITS);
+        let zero = x.bvxor(&x);
+        let one = zero.bvnot().bvneg();
+
+        Executor {
+            ctx,
+            solver: Solver::new(ctx),
+            zero,
+            one,
+            numerals: HashMap::new()
+        }
+    }
+
+    fn numeral(&mut self, v: u64) -> Ast<'ctx> {
+        if let Some(n) = self.numerals.get(&v) {
+            return n.clone();
+        }
+
+        let mut n = self.zero.clone();
+        for bit in (0..BITS).rev() {
+            n = n.bvadd(&n);
+            if v >> bit & 1 == 1 {
+                n = n.bvadd(&self.one);
+            }
+        }
+
+        self.numerals.insert(v, n.clone());
+        n
+    }
+
+    fn ast(&mut self, v: &Value<'ctx>) -> Ast<'ctx> {
+        match v {
+            Value::Const(c) => self.numeral(*c),
+            Value::Sym(a) => a.clone()
+        }
+    }
+
+    fn operand(&self, s: &State<'ctx>, o: Operand) -> Value<'ctx> {
+        match o {
+            Operand::Reg(r) => s.regs[r.index()].clone(),
+            Operand::Imm(v) => Value::Const(v)
+        }
+    }
+
+    fn eval(&mut self, s: &State<'ctx>, e: &Expr) -> Value<'ctx> {
+        let (op, a, b) = match *e {
+            Expr::Value(a) => return self.operand(s, a),
+            Expr::Bin(op, a, b) => (op, self.operand(s, a), self.operand(s, b))
+        };
+
+        if let (Value::Const(a), Value::Const(b)) = (&a, &b) {
+            return Value::Const(op.apply(*a, *b));
+        }
+
+        let a = self.ast(&a);
+        let b = self.ast(&b);
+        let v = match op {
+            BinOp::Add => a.bvadd(&b),
+            BinOp::Mul => a.bvmul(&b),
+            BinOp::And => a.bvand(&b),
+            BinOp::Or => a.bvor(&b),
+            BinOp::Gt => a.bvugt(&b).ite(&self.one, &self.zero),
+            BinOp::Eq => a._eq(&b).ite(&self.one, &self.zero)
+        };
+
+        Value::Sym(v)
+    }
+
+    fn cond(&mut self, s: &State<'ctx>, c: &Cond) -> Ast<'ctx> {
+        let a = s.regs[c.reg.index()].clone();
+        let b = self.operand(s, c.rhs);
+
+        if let (Value::Const(a), Value::Const(b)) = (&a, &b) {
+            let holds = match c.cmp {
+                Cmp::Eq => a == b,
+                Cmp::Ne => a != b,
+                Cmp::Lt => a < b,
+                Cmp::Le => a <= b,
+                Cmp::Gt => a > b,
+                Cmp::Ge => a >= b
+            };
+            return Ast::from_bool(self.ctx, holds);
+        }
+
+        let a = self.ast(&a);
+        let b = self.ast(&b);
+        match c.cmp {
+            Cmp::Eq => a._eq(&b),
+            Cmp::Ne => a._eq(&b).not(),
+            Cmp::Lt => a.bvult(&b),
+            Cmp::Le => a.bvule(&b),
+            Cmp::Gt => a.bvugt(&b),
+            Cmp::Ge => a.bvuge(&b)
+        }
+    }
+
+    ///Checks the path condition with additional constraints, returns
+    ///the values of `asts` in a model if it is satisfiable
+    fn check(&self, path: &[Ast<'ctx>], extra: &[Ast<'ctx>], asts: &[Ast<'ctx>]) -> Option<Vec<u64>> {
+        self.solver.push();
+        for c in path.iter().chain(extra.iter()) {
+            self.solver.assert(c);
+        }
+
+        let result = if self.solver.check() {
+            let model = self.solver.get_model();
+            asts.iter()
+                .map(|a| model.eval(a).and_then(|v| v.as_u64()))
+                .collect()
+        } else {
+            None
+        };
+
+        self.solver.pop(1);
+        result
+    }
+
+    ///Concrete values a jump target can take on the path
+    fn targets(&mut self, path: &[Ast<'ctx>], target: &Ast<'ctx>) -> Result<Vec<u64>> {
+        let mut excluded = Vec::new();
+        let mut values = Vec::new();
+
+        while let Some(v) = self.check(path, &excluded, std::slice::from_ref(target)) {
+            if values.len() == MAX_FORKS {
+                return Err(format!("Computed jump with more than {} targets", MAX_FORKS).into());
+            }
+
+            let v = v[0];
+            values.push(v);
+            let n = self.numeral(v);
+            excluded.push(target._eq(&n).not());
+        }
+
+        Ok(values)
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Pending(Reverse<usize>, Reverse<usize>);
+
+///Searches the paths of `p` starting at `init` for initial values of the
+///symbolic registers that reach the goal within `max_steps` instructions.
+///Returns the witness with the fewest steps.
+pub fn solve(p: &Program, init: &Cpu, q: &Query) -> Result<Option<Witness>> {
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let mut ex = Executor::new(&ctx);
+
+    let mut regs: Vec<Value> = init.registers().iter()
+        .map(|&v| Value::Const(v))
+        .collect();
+    let mut initial = Vec::new();
+    for r in q.symbolic.iter() {
+        let a = ctx.fresh_bitvector_const(&format!("r{}", r.index()), BITS);
+        regs[r.index()] = Value::Sym(a.clone());
+        initial.push((*r, a));
+    }
+
+    let mut start = State { regs, ip: init.ip(), steps: 0, path: Vec::new() };
+    start.path = q.assume.iter()
+        .map(|c| ex.cond(&start, c))
+        .collect();
+
+    let initial_asts: Vec<Ast> = initial.iter().map(|(_, a)| a.clone()).collect();
+    let witness = |values: Vec<u64>, steps: usize| {
+        let mut registers = init.registers().to_vec();
+        for ((r, _), v) in initial.iter().zip(values) {
+            registers[r.index()] = v;
+        }
+        Witness { registers, steps }
+    };
+
+    let mut states = vec![Some(start)];
+    let mut queue = BinaryHeap::new();
+    queue.push(Pending(Reverse(0), Reverse(0)));
+
+    while let Some(Pending(_, Reverse(id))) = queue.pop() {
+        let mut s = states[id].take().unwrap();
+        let bound = queue.peek().map_or(usize::MAX, |p| (p.0).0);
+
+        while s.steps <= bound {
+            let halted = s.ip >= p.ops.len();
+            let at_goal = match q.goal {
+                Goal::Halt => halted,
+                Goal::Reach(t) => s.ip == t
+            };
+
+            if at_goal {
+                let extra: Vec<Ast> = q.require.iter()
+                    .map(|c| ex.cond(&s, c))
+                    .collect();
+                if let Some(values) = ex.check(&s.path, &extra, &initial_asts) {
+                    return Ok(Some(witness(values, s.steps)));
+                }
+            }
+
+            if halted || at_goal || s.steps >= q.max_steps {
+                break;
+            }
+
+            let i = s.ip;
+            let e = resolved_expr(p, i);
+            let v = ex.eval(&s, &e);
+            let target = p.ops[i].target();
+            s.steps += 1;
+
+            let ip = match p.ip_reg {
+                Some(ip) => ip,
+                None => {
+                    s.regs[target.index()] = v;
+                    s.ip += 1;
+                    continue;
+                }
+            };
+
+            s.regs[ip.index()] = Value::Const(i as u64);
+            s.regs[target.index()] = v;
+
+            match s.regs[ip.index()].clone() {
+                Value::Const(c) => s.ip = (c as usize).saturating_add(1),
+                Value::Sym(a) => {
+                    for c in ex.targets(&s.path, &a)? {
+                        let cond = a._eq(&ex.numeral(c));
+                        let mut fork = s.clone();
+                        fork.regs[ip.index()] = Value::Const(c);
+                        fork.ip = (c as usize).saturating_add(1);
+                        fork.path.push(cond);
+
+                        queue.push(Pending(Reverse(fork.steps), Reverse(states.len())));
+                        states.push(Some(fork));
+                    }
+                    break;
+                }
+            }
+
+            //Another state is behind, continue with it
+            if s.steps > bound {
+                queue.push(Pending(Reverse(s.steps), Reverse(states.len())));
+                states.push(Some(s));
+                break;
+            }
+        }
+    }
+
+    Ok(None)
+}
@@ -3,8 +3,15 @@ extern crate regex;
 extern crate lazy_static;
 
 extern crate itertools;
+extern crate petgraph;
 extern crate z3;
+extern crate num_traits;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
+pub mod device;
 pub mod solutions;
 pub mod util;
 
@@ -15,6 +22,23 @@ pub trait Solution {
     fn part1(&mut self) -> Result<()>;
     fn part2(&mut self) -> Result<()>;
 
+    fn animate(&mut self) -> Result<()> {
+        Err("No animation for this solution".into())
+    }
+
+    fn debug(&mut self) -> Result<()> {
+        Err("No debugger for this solution".into())
+    }
+
+    fn profile(&mut self) -> Result<()> {
+        Err("No profiler for this solution".into())
+    }
+
+    ///Compares slow and fast implementations on generated inputs
+    fn diff(&mut self) -> Result<()> {
+        Err("No differential test for this solution".into())
+    }
+
     fn run(&mut self) -> Result<()> {
         self.init()?;
         self.part1()?;
@@ -5,7 +5,11 @@ use aoc::Result;
 
 
 fn main() -> Result<()> {
-    let n = 11;
+    let n = 12;
+    let animate = std::env::args().any(|a| a == "--animate");
+    let debug = std::env::args().any(|a| a == "--debug");
+    let profile = std::env::args().any(|a| a == "--profile");
+    let diff = std::env::args().any(|a| a == "--diff");
     let mut solutions = solutions::get_solutions();
 
     match n {
@@ -15,7 +19,22 @@ fn main() -> Result<()> {
             }
         },
         n => {
-            solutions.get_mut(n-1).unwrap().run()?
+            let solution = solutions.get_mut(n-1).unwrap();
+            if animate {
+                solution.init()?;
+                solution.animate()?
+            } else if debug {
+                solution.init()?;
+                solution.debug()?
+            } else if profile {
+                solution.init()?;
+                solution.profile()?
+            } else if diff {
+                solution.init()?;
+                solution.diff()?
+            } else {
+                solution.run()?
+            }
         }
     };
 
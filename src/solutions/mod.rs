@@ -2,6 +2,7 @@ use Solution;
 
 pub mod solution_01;
 pub mod solution_02;
+pub mod solution_11;
 pub mod solution_12;
 pub mod solution_13;
 pub mod solution_14;
@@ -21,6 +22,7 @@ pub mod solution_25;
 
 use solutions::solution_01::Solution01;
 use solutions::solution_02::Solution02;
+use solutions::solution_11::Solution11;
 use solutions::solution_12::Solution12;
 use solutions::solution_13::Solution13;
 use solutions::solution_14::Solution14;
@@ -41,6 +43,7 @@ pub fn get_solutions() -> Vec<Box<Solution>> {
     vec![
         Box::new(Solution01::default()),
         Box::new(Solution02::default()),
+        Box::new(Solution11::default()),
         Box::new(Solution12::default()),
         Box::new(Solution13::default()),
         Box::new(Solution14::default()),
@@ -0,0 +1,72 @@
+use Solution;
+use Result;
+use util::mat2::Mat2;
+use util::mat2::Pos;
+use util::summed_area::SummedAreaTable;
+
+const N: usize = 300;
+
+#[derive(Default)]
+pub struct Solution11 {
+    serial: i64
+}
+
+fn power_level(p: Pos, serial: i64) -> i64 {
+    //Grid coordinates start at 1
+    let x = (p.x + 1) as i64;
+    let y = (p.y + 1) as i64;
+
+    let rack_id = x + 10;
+    let power = (rack_id * y + serial) * rack_id;
+    (power / 100) % 10 - 5
+}
+
+fn build_table(serial: i64) -> SummedAreaTable<i64> {
+    let mut grid = Mat2::new(N, N);
+    for p in grid.coords() {
+        grid[p] = power_level(p, serial);
+    }
+
+    SummedAreaTable::new(&grid)
+}
+
+fn part1(serial: i64) -> Result<(usize, usize)> {
+    let table = build_table(serial);
+    let (p, _) = table.best_window(3)
+        .ok_or("No window found")?;
+
+    Ok((p.x + 1, p.y + 1))
+}
+
+fn part2(serial: i64) -> Result<(usize, usize, usize)> {
+    let table = build_table(serial);
+    let (k, p, _) = table.best_window_any()
+        .ok_or("No window found")?;
+
+    Ok((p.x + 1, p.y + 1, k))
+}
+
+impl Solution for Solution11 {
+    fn init(&mut self) -> Result<()> {
+        self.serial = 4172;
+        Ok(())
+    }
+
+    fn part1(&mut self) -> Result<()> {
+        assert_eq!((33, 45), part1(18)?);
+        assert_eq!((21, 61), part1(42)?);
+
+        let result = part1(self.serial)?;
+        println!("result1: {:?}", result);
+        Ok(())
+    }
+
+    fn part2(&mut self) -> Result<()> {
+        assert_eq!((90, 269, 16), part2(18)?);
+        assert_eq!((232, 251, 12), part2(42)?);
+
+        let result = part2(self.serial)?;
+        println!("result2: {:?}", result);
+        Ok(())
+    }
+}
@@ -5,11 +5,12 @@ use regex::Regex;
 use util::file::load;
 use std::str::FromStr;
 use util::file::sample_path;
+use util::cycle::fast_forward_with_drift;
 
 type Pattern = [Pot; 5];
 
 
-#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialOrd, PartialEq, Eq, Hash, Copy, Clone)]
 enum Pot {
     NoPlant,
     Plant,
@@ -21,6 +22,13 @@ struct PatternMatch {
     pot: Pot,
 }
 
+//Pots from the first to the last plant, `left` is the number of the first pot
+#[derive(Debug, Clone)]
+struct Row {
+    pots: Vec<Pot>,
+    left: isize
+}
+
 #[derive(Default)]
 struct Data {
     state: Vec<Pot>,
@@ -37,6 +45,33 @@ impl Pot {
     }
 }
 
+impl Row {
+    fn new(mut pots: Vec<Pot>, mut left: isize) -> Row {
+        match pots.iter().position(|&p| p == Pot::Plant) {
+            Some(first) => {
+                let last = pots.iter().rposition(|&p| p == Pot::Plant).unwrap();
+                pots.truncate(last + 1);
+                pots.drain(..first);
+                left += first as isize;
+            }
+            None => pots.clear()
+        }
+
+        Row {
+            pots,
+            left
+        }
+    }
+
+    fn sum(&self) -> isize {
+        self.pots.iter()
+            .enumerate()
+            .filter(|(_, &p)| p == Pot::Plant)
+            .map(|(i, _)| self.left + i as isize)
+            .sum()
+    }
+}
+
 impl Default for Pot {
     fn default() -> Self {
         Pot::NoPlant
@@ -142,53 +177,32 @@ impl Data {
             Data::pot_to_num(data[4]) << 4
     }
 
-    fn run(&self, gens: usize) -> isize {
-        let mut buffer = self.state.clone();
-        let mut back_buffer = self.state.clone();
-
-        let mut left_index = 0;
-
-        for _ in 1..=gens {
-            let n = buffer.len();
-
-            let first = buffer.iter().position(|&p| p == Pot::Plant).unwrap();
-            let last = buffer.iter().rposition(|&p| p == Pot::Plant).unwrap();
-
-
-            let right_left = (n - last - 1) as isize;
-            let left_fill = (4-(first as isize)).max(0) as usize;
-            let right_fill = (4 - right_left).max(0) as usize;
-
-            let m = n + left_fill + right_fill;
-            left_index += left_fill;
-            back_buffer.resize(m, Pot::NoPlant);
-
-            for i in 0..m {
-                let i = i as isize;
-                let p = get_pattern(&buffer, i - (left_fill as isize) - 2);
-                let c = self.find_match(p)
-                    .unwrap_or(Pot::NoPlant);
-
-                back_buffer[i as usize] = c;
-            }
-
+    fn next_gen(&self, row: &Row) -> Row {
+        //Only pots up to two away from a plant can change
+        let n = row.pots.len() as isize;
+        let pots = (-2..n + 2)
+            .map(|i| self.find_match(get_pattern(&row.pots, i))
+                .unwrap_or(Pot::NoPlant))
+            .collect();
 
-            std::mem::swap(&mut buffer, &mut back_buffer);
-        }
+        Row::new(pots, row.left - 2)
+    }
 
-        let sum: isize = buffer.iter()
-            .enumerate()
-            .map(|(i, p)| {
-                match p {
-                    Pot::NoPlant => 0,
-                    Pot::Plant => {
-                        (i as isize) - (left_index as isize)
-                    }
-                }
-            })
-            .sum();
-
-        sum
+    fn run(&self, gens: usize) -> isize {
+        let row = Row::new(self.state.clone(), 0);
+
+        let row = fast_forward_with_drift(
+            row,
+            |r| self.next_gen(r),
+            |r| r.pots.clone(),
+            |first, repeat, cycles| Row {
+                pots: repeat.pots.clone(),
+                left: repeat.left + (repeat.left - first.left) * (cycles as isize)
+            },
+            gens
+        );
+
+        row.sum()
     }
 }
 
@@ -198,29 +212,14 @@ pub struct Solution12 {
     sample: Data,
 }
 
-fn get_pattern(data: &[Pot], ix: isize) -> Pattern {
+fn get_pattern(data: &[Pot], center: isize) -> Pattern {
     let mut pat = [Pot::NoPlant; 5];
-    match ix {
-        ix if ix < 0  => {
-            let fill = (5+ix).max(0) as usize;
-            let off = ix.abs() as usize;
-            for i in 0..fill {
-                pat[off+i] = data[i];
-            }
-
-        },
-        ix if (ix as usize)+5 > data.len() => {
-            let fill = ((data.len() as isize) - ix).max(0) as usize;
-            let ix = ix as usize;
-            for i in 0..fill {
-                pat[i] = data[ix+i];
-            }
-        },
-        ix => {
-            let ix = ix as usize;
-            pat.copy_from_slice(&data[ix..ix+5]);
+    for (k, pot) in pat.iter_mut().enumerate() {
+        let i = center + (k as isize) - 2;
+        if i >= 0 && (i as usize) < data.len() {
+            *pot = data[i as usize];
         }
-    };
+    }
 
     pat
 }
@@ -242,11 +241,8 @@ impl Solution for Solution12 {
     }
 
     fn part2(&mut self) -> Result<()> {
-        let mut n: usize = 500;
-        for _ in 0..3 {
-            println!("result[{}] : {}", n, self.data.run(n));
-            n *= 10;
-        }
+        let sum = self.data.run(50_000_000_000);
+        println!("result2: {}", sum);
         Ok(())
     }
 }
\ No newline at end of file
@@ -5,6 +5,10 @@ use std::str::FromStr;
 use util::file::sample_path;
 use util::file::load;
 use std::io::Write;
+use util::mat2::Mat2;
+use util::mat2::Pos;
+use util::player::Animate;
+use util::player::Player;
 
 #[derive(Copy, Clone, Debug)]
 enum Direction {
@@ -31,7 +35,7 @@ struct Cart {
     crashed: bool
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 struct Grid {
     grid: Vec<Option<Path>>,
     carts: Vec<Cart>,
@@ -50,7 +54,6 @@ impl Direction {
         })
     }
 
-    #[allow(dead_code)]
     fn to_char(&self) -> char {
         use self::Direction::*;
         match self {
@@ -96,7 +99,6 @@ impl Path {
         })
     }
 
-    #[allow(dead_code)]
     fn to_char(&self) -> char {
         match self {
             Path::HorizontalRoad => '-',
@@ -312,6 +314,33 @@ impl Grid {
     }
 }
 
+impl Animate for Grid {
+    fn frame(&self) -> Mat2<char> {
+        let mut frame = Mat2::new_with(self.h, self.w, ' ');
+        for p in frame.coords() {
+            if let Some(path) = self.get(p.x, p.y) {
+                frame[p] = path.to_char();
+            }
+        }
+
+        for cart in self.carts.iter() {
+            let p = Pos::new(cart.pos.0, cart.pos.1);
+            frame[p] = if cart.crashed { 'X' } else { cart.dir.to_char() };
+        }
+
+        frame
+    }
+
+    fn step(&mut self) -> bool {
+        if self.carts.iter().filter(|c| !c.crashed).count() <= 1 {
+            return false;
+        }
+
+        self.tick();
+        true
+    }
+}
+
 #[derive(Default)]
 pub struct Solution13 {
     grid: Grid,
@@ -349,4 +378,8 @@ impl Solution for Solution13 {
         println!("result2: {:?}", result);
         Ok(())
     }
+
+    fn animate(&mut self) -> Result<()> {
+        Player::default().play(&mut self.grid.clone())
+    }
 }
\ No newline at end of file
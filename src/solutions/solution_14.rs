@@ -1,5 +1,9 @@
 use Solution;
 use Result;
+use util::diff::differential;
+use util::diff::report;
+use util::diff::shrink_u64;
+use util::diff::shrink_vec;
 
 #[derive(Default)]
 pub struct Solution14 {
@@ -55,31 +59,53 @@ fn part1(val: u64, n: usize) -> Result<u64> {
     Ok(get_num(&r[n..n + 10]))
 }
 
-fn part2(val: u64, pat: u64) -> Result<usize> {
+///Recipes to the left of the first occurrence of the digits `pat`
+fn part2(val: u64, pat: &[u8]) -> Result<usize> {
     let mut r = get_data(val);
     let (mut e1, mut e2) = (0, 1);
-    let m = get_data(pat).len();
+    let m = pat.len();
+    //Windows starting before `next` were already compared
+    let mut next = 0;
+
+    loop {
+        while next + m <= r.len() {
+            if &r[next..next + m] == pat {
+                return Ok(next);
+            }
+            next += 1;
+        }
 
-    let result = loop {
         combine(&mut r, &mut e1, &mut e2);
+    }
+}
 
-        let n = r.len();
+///Recipes the differential test patterns are cut from
+const DIFF_BOARD: usize = 3000;
+const DIFF_CASES: usize = 300;
+const DIFF_SEED: u64 = 14;
 
-        if r.len() > m + 1 {
-            let n = n - 1;
-            if get_num(&r[n - m..n]) == pat {
-                break n - m;
-            }
-        }
+fn scoreboard(val: u64, n: usize) -> Vec<u8> {
+    let mut r = get_data(val);
+    let (mut e1, mut e2) = (0, 1);
 
-        if n > m {
-            if get_num(&r[n - m..]) == pat {
-                break n - m;
-            }
-        }
-    };
+    while r.len() < n {
+        combine(&mut r, &mut e1, &mut e2);
+    }
 
-    Ok(result)
+    r
+}
+
+///Grows the scoreboard and searches all of it after every round
+fn part2_naive(val: u64, pat: &[u8]) -> usize {
+    let mut r = get_data(val);
+    let (mut e1, mut e2) = (0, 1);
+
+    loop {
+        if let Some(ix) = r.windows(pat.len()).position(|w| w == pat) {
+            return ix;
+        }
+        combine(&mut r, &mut e1, &mut e2);
+    }
 }
 
 impl Solution for Solution14 {
@@ -103,12 +129,33 @@ impl Solution for Solution14 {
     }
 
     fn part2(&mut self) -> Result<()> {
-        assert_eq!(9, part2(self.data, 51589)?);
-        assert_eq!(18, part2(self.data, 92510)?);
-        assert_eq!(2018, part2(self.data, 59414)?);
+        assert_eq!(9, part2(self.data, &get_data(51589))?);
+        assert_eq!(18, part2(self.data, &get_data(92510))?);
+        assert_eq!(2018, part2(self.data, &get_data(59414))?);
 
-        let result = part2(self.data, 260321)?;
+        let result = part2(self.data, &get_data(260321))?;
         println!("result2: {}", result);
         Ok(())
     }
+
+    ///Patterns are cut from the start of the scoreboard, so both searches end
+    fn diff(&mut self) -> Result<()> {
+        let val = self.data;
+        let board = scoreboard(val, DIFF_BOARD);
+        let occurs = |pat: &[u8]| board.windows(pat.len()).any(|w| w == pat);
+
+        let m = differential(DIFF_CASES, DIFF_SEED,
+                             |rng| {
+                                 let len = rng.range(1, 6) as usize;
+                                 let start = rng.below(DIFF_BOARD - len);
+                                 board[start..start + len].to_vec()
+                             },
+                             |pat| shrink_vec(pat, |&d| shrink_u64(d as u64).into_iter().map(|d| d as u8).collect())
+                                 .into_iter()
+                                 .filter(|p| !p.is_empty() && occurs(p))
+                                 .collect(),
+                             |pat| Some(part2_naive(val, pat)),
+                             |pat| part2(val, pat).ok());
+        report("recipe search", DIFF_CASES, m)
+    }
 }
\ No newline at end of file
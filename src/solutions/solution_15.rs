@@ -1,15 +1,24 @@
 use Solution;
 use Result;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use util::file::data_path;
 use util::mat2::Mat2;
-use std::usize;
 use util::mat2::Pos;
-use std::collections::VecDeque;
 use util::file::sample_path;
 use util::file::load;
+use util::file::scenario_path;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::Write;
+use util::player::Animate;
+use util::player::Player;
 
 /*
 - wall: #, open cavern: ., goblin: G, elf: E
@@ -19,15 +28,18 @@ use std::fmt::Formatter;
 - end if no targets remain
 */
 
-#[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
-enum UnitKind {
-    Elf,
-    Goblin,
-}
+///Index into the factions of a `Scenario`
+#[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash)]
+struct Faction(usize);
+
+///Faction of the elves in the puzzle scenario
+const ELF: Faction = Faction(0);
 
 #[derive(Debug, Clone)]
 struct Unit {
-    kind: UnitKind,
+    faction: Faction,
+    ///Map glyph the unit was placed with
+    glyph: char,
     hp: u64,
     atk: u64,
     id: usize,
@@ -46,56 +58,319 @@ struct Grid {
 }
 
 
+///Unit stats and hostilities of a battle, read as JSON. The default is the
+///puzzle: elves `E` and goblins `G` with 200 hit points and attack 3.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scenario {
+    factions: Vec<FactionSpec>,
+    ///`hostile[a][b]` if units of faction `a` attack units of faction `b`,
+    ///all different factions are hostile if missing
+    #[serde(default)]
+    hostile: Option<Vec<Vec<bool>>>,
+    ///Units with stats of their own, like a goblin boss `B`,
+    ///or all units of a faction glyph
+    #[serde(default)]
+    glyphs: HashMap<char, GlyphSpec>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FactionSpec {
+    name: String,
+    glyph: char,
+    hp: u64,
+    atk: u64,
+    #[serde(default)]
+    strategy: StrategySpec
+}
+
+///Built-in strategies, as named in scenarios and the debugger
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum StrategySpec {
+    #[default]
+    Puzzle,
+    FocusFire,
+    Retreat { below: u64 },
+    Hold
+}
+
+///How a unit plays its turn: it moves at most one square, then attacks
+///at most one adjacent enemy
+trait Strategy: std::fmt::Debug + Send + Sync {
+    ///Open square next to the unit at `p` to move to, if it moves
+    fn step(&self, cave: &Cave, p: Pos) -> Option<Pos>;
+
+    ///Adjacent enemy to attack after moving
+    fn target(&self, cave: &Cave, p: Pos) -> Option<Pos> {
+        cave.find_close_enemy(p)
+    }
+}
+
+///Rules of the puzzle: stay next to an enemy, otherwise step towards the nearest
+#[derive(Debug)]
+struct Puzzle;
+
+///Every unit goes for the enemy with the fewest hit points,
+///for the nearest if it cannot reach that one
+#[derive(Debug)]
+struct FocusFire;
+
+///Fights like the puzzle, but with less than `below` hit points the unit
+///stops advancing and steps away from adjacent enemies where it can
+#[derive(Debug)]
+struct Retreat {
+    below: u64
+}
+
+///Never moves, attacks what comes close
+#[derive(Debug)]
+struct Hold;
+
+///Overrides the stats of the faction for units of a glyph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GlyphSpec {
+    faction: String,
+    hp: Option<u64>,
+    atk: Option<u64>
+}
+
 #[derive(Debug, Clone, Default)]
 struct Cave {
     grids: Mat2<Grid>,
+    scenario: Scenario,
+    ///Strategy of each faction, the puzzle rules if missing
+    strategies: Vec<Arc<dyn Strategy>>,
     w: usize,
-    h: usize
+    h: usize,
+    ///Completed rounds
+    round: usize
 }
 
-impl Unit {
-    fn from_char(c: char, id: usize) -> Result<Unit> {
-        let kind = match c {
-            'G' => UnitKind::Goblin,
-            'E' => UnitKind::Elf,
-            _ => return Err("Invalid unit kind".into())
+///What happened during a round, units are named by id
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    Moved { unit: usize, from: Pos, to: Pos },
+    Attacked { unit: usize, target: usize, at: Pos, damage: u64 },
+    Died { unit: usize, at: Pos },
+    RoundCompleted { round: usize }
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        let faction = |name: &str, glyph| FactionSpec {
+            name: name.to_string(),
+            glyph,
+            hp: 200,
+            atk: 3,
+            strategy: StrategySpec::Puzzle
+        };
+
+        Scenario {
+            factions: vec![faction("elf", 'E'), faction("goblin", 'G')],
+            hostile: None,
+            glyphs: HashMap::new()
+        }
+    }
+}
+
+impl Scenario {
+    fn load(path: &str) -> Result<Scenario> {
+        let scenario: Scenario = serde_json::from_str(&load(path)?)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let n = self.factions.len();
+        if let Some(m) = &self.hostile {
+            if m.len() != n || m.iter().any(|row| row.len() != n) {
+                return Err(format!("Hostility matrix must be {0}x{0}", n).into());
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for f in &self.factions {
+            if !seen.insert(f.glyph) {
+                return Err(format!("Glyph {} used by two factions", f.glyph).into());
+            }
+        }
+
+        let glyphs = self.factions.iter().map(|f| f.glyph).chain(self.glyphs.keys().cloned());
+        for g in glyphs {
+            if g == '#' || g == '.' || g.is_whitespace() {
+                return Err(format!("Glyph {:?} is not a unit", g).into());
+            }
+        }
+
+        for (g, spec) in &self.glyphs {
+            self.faction(&spec.faction)
+                .ok_or_else(|| format!("Unknown faction {} for glyph {}", spec.faction, g))?;
+        }
+
+        if self.factions.iter().any(|f| f.hp == 0) || self.glyphs.values().any(|g| g.hp == Some(0)) {
+            return Err("Units need hit points".into());
+        }
+
+        Ok(())
+    }
+
+    fn faction(&self, name: &str) -> Option<Faction> {
+        self.factions.iter()
+            .position(|f| f.name == name)
+            .map(Faction)
+    }
+
+    fn name(&self, f: Faction) -> &str {
+        &self.factions[f.0].name
+    }
+
+    fn hostile(&self, a: Faction, b: Faction) -> bool {
+        match &self.hostile {
+            Some(m) => m[a.0][b.0],
+            None => a != b
+        }
+    }
+
+    ///Unit placed with glyph `c`
+    fn unit(&self, c: char, id: usize) -> Result<Unit> {
+        let (faction, hp, atk) = match self.glyphs.get(&c) {
+            Some(g) => {
+                let f = self.faction(&g.faction).ok_or("Unknown faction")?;
+                let spec = &self.factions[f.0];
+                (f, g.hp.unwrap_or(spec.hp), g.atk.unwrap_or(spec.atk))
+            }
+            None => {
+                let f = self.factions.iter()
+                    .position(|f| f.glyph == c)
+                    .ok_or_else(|| format!("Invalid unit glyph {}", c))?;
+                (Faction(f), self.factions[f].hp, self.factions[f].atk)
+            }
         };
 
         Ok(Unit {
-            kind,
+            faction,
+            glyph: c,
             id,
-            hp: 200,
-            atk: 3,
+            hp,
+            atk
         })
     }
+}
 
-    fn can_attack(&self, other: &Unit) -> bool {
-        self.kind != other.kind
+impl StrategySpec {
+    fn build(self) -> Arc<dyn Strategy> {
+        match self {
+            StrategySpec::Puzzle => Arc::new(Puzzle),
+            StrategySpec::FocusFire => Arc::new(FocusFire),
+            StrategySpec::Retreat { below } => Arc::new(Retreat { below }),
+            StrategySpec::Hold => Arc::new(Hold)
+        }
     }
 
-    fn to_char(&self) -> char {
-        let n = (self.id & 0xf) as u8;
+    ///All built-in strategies, retreating below a quarter of `hp`
+    fn all(hp: u64) -> Vec<StrategySpec> {
+        vec![
+            StrategySpec::Puzzle,
+            StrategySpec::FocusFire,
+            StrategySpec::Retreat { below: hp / 4 },
+            StrategySpec::Hold
+        ]
+    }
+}
 
-        let base = match self.kind {
-            UnitKind::Goblin => b'A',
-            UnitKind::Elf => b'0'
-        };
-        let n = base + n;
-        n as char
+impl Display for StrategySpec {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            StrategySpec::Puzzle => write!(f, "puzzle"),
+            StrategySpec::FocusFire => write!(f, "focus_fire"),
+            StrategySpec::Retreat { below } => write!(f, "retreat {}", below),
+            StrategySpec::Hold => write!(f, "hold")
+        }
+    }
+}
+
+impl FromStr for StrategySpec {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts[..] {
+            ["puzzle"] => Ok(StrategySpec::Puzzle),
+            ["focus_fire"] => Ok(StrategySpec::FocusFire),
+            ["retreat", below] => Ok(StrategySpec::Retreat { below: below.parse()? }),
+            ["hold"] => Ok(StrategySpec::Hold),
+            _ => Err(format!("Invalid strategy {}", s).into())
+        }
     }
+}
+
+impl Strategy for Puzzle {
+    fn step(&self, cave: &Cave, p: Pos) -> Option<Pos> {
+        if cave.find_close_enemy(p).is_some() {
+            return None;
+        }
+
+        let unit = cave.unit(p);
+        cave.next_step(p, |u| cave.can_attack(unit, u))
+    }
+}
+
+impl Strategy for FocusFire {
+    fn step(&self, cave: &Cave, p: Pos) -> Option<Pos> {
+        if cave.find_close_enemy(p).is_some() {
+            return None;
+        }
 
+        let unit = cave.unit(p);
+        let weakest = cave.grids.coords()
+            .filter_map(|q| cave.grids[q].unit.as_ref().map(|u| (q, u)))
+            .filter(|(_, u)| cave.can_attack(unit, u))
+            .min_by_key(|&(q, u)| (u.hp, reading(q)))
+            .map(|(_, u)| u.id)?;
+
+        cave.next_step(p, |u| u.id == weakest)
+            .or_else(|| Puzzle.step(cave, p))
+    }
+}
+
+impl Strategy for Retreat {
+    fn step(&self, cave: &Cave, p: Pos) -> Option<Pos> {
+        let unit = cave.unit(p);
+        if unit.hp >= self.below {
+            return Puzzle.step(cave, p);
+        }
+
+        //Enemies next to a square
+        let threat = |q: Pos| cave.neighbors(q)
+            .filter(|&n| cave.grids[n].unit.as_ref().is_some_and(|u| cave.can_attack(unit, u)))
+            .count();
+
+        let here = threat(p);
+        cave.neighbors(p)
+            .filter(|&n| !cave.grids[n].is_blocked())
+            .map(|n| (n, threat(n)))
+            .filter(|&(_, t)| t < here)
+            .min_by_key(|&(n, t)| (t, reading(n)))
+            .map(|(n, _)| n)
+    }
+}
+
+impl Strategy for Hold {
+    fn step(&self, _cave: &Cave, _p: Pos) -> Option<Pos> {
+        None
+    }
+}
+
+impl Unit {
     fn damage(&mut self, dmg: u64) -> bool {
         let atk = dmg.min(self.hp);
         self.hp -= atk;
         self.hp == 0
     }
-
-    fn opponent_kind(&self) -> UnitKind {
-        match self.kind {
-            UnitKind::Goblin => UnitKind::Elf,
-            UnitKind::Elf => UnitKind::Goblin
-        }
-    }
 }
 
 
@@ -109,12 +384,12 @@ impl Default for Grid {
 }
 
 impl Grid {
-    fn from_char(c: char, id: usize) -> Result<Grid> {
+    fn from_char(c: char, id: usize, scenario: &Scenario) -> Result<Grid> {
         let (unit, kind) = match c {
             '.' => (None, GridKind::Open),
             '#' => (None, GridKind::Wall),
             c => {
-                let unit = Unit::from_char(c, id)?;
+                let unit = scenario.unit(c, id)?;
                 (Some(unit), GridKind::Open)
             }
         };
@@ -127,7 +402,7 @@ impl Grid {
 
     fn to_char(&self) -> char {
         match (&self.unit, &self.kind) {
-            (Some(u), _) => u.to_char(),
+            (Some(u), _) => u.glyph,
             (None, GridKind::Wall) => '#',
             (None, GridKind::Open) => '.'
         }
@@ -145,56 +420,94 @@ impl Display for Grid {
 }
 
 impl Cave {
-    fn new(h: usize, w: usize) -> Cave {
+    fn new(h: usize, w: usize, scenario: Scenario) -> Cave {
         Cave {
             grids: Mat2::new(h, w),
+            strategies: scenario.factions.iter()
+                .map(|f| f.strategy.build())
+                .collect(),
+            scenario,
             h,
-            w
+            w,
+            round: 0
         }
     }
 
-    fn elfs(&self) -> usize {
+    ///Parses the map with the units of `scenario`
+    fn parse_with(s: &str, scenario: Scenario) -> Result<Cave> {
+        let h = s.lines().count();
+        let w = s.lines().next().ok_or("Empty map")?.len();
+
+        let mut cave = Cave::new(h, w, scenario);
+        let mut id = 1;
+        for (y, l) in s.lines().enumerate() {
+            for (x, c) in l.chars().enumerate() {
+                let grid = Grid::from_char(c, id, &cave.scenario)?;
+                if grid.unit.is_some() {
+                    id += 1;
+                }
+
+                cave.grids[Pos::new(x, y)] = grid;
+            }
+        }
+
+        Ok(cave)
+    }
+
+    fn count(&self, f: Faction) -> usize {
         self.units()
-            .filter(|u| u.kind == UnitKind::Elf)
+            .filter(|u| u.faction == f)
             .count()
     }
 
-    fn winner(&self) -> Option<UnitKind> {
-        let (mut elfs, mut gobs) = (0 , 0);
-        for u in self.units() {
-            match u.kind {
-                UnitKind::Elf => elfs += 1,
-                UnitKind::Goblin => gobs += 1
-            };
-        }
-
-        match (elfs, gobs) {
-            (0, _) => Some(UnitKind::Goblin),
-            (_, 0) => Some(UnitKind::Elf),
-            _ => None
-        }
+    ///Factions with units left
+    fn survivors(&self) -> Vec<Faction> {
+        let mut factions: Vec<Faction> = self.units().map(|u| u.faction).collect();
+        factions.sort();
+        factions.dedup();
+        factions
     }
 
     fn is_done(&self) -> bool {
-        self.winner().is_some()
+        let survivors = self.survivors();
+        !survivors.iter()
+            .any(|&a| survivors.iter().any(|&b| self.scenario.hostile(a, b)))
     }
 
-
-    fn upgrade_elf_weapons(&mut self, atk: u64) {
+    fn set_attack(&mut self, f: Faction, atk: u64) {
         for p in self.grids.coords() {
             let g = &mut self.grids[p];
             if let Some(ref mut unit) = g.unit {
-                if unit.kind == UnitKind::Elf {
+                if unit.faction == f {
                     unit.atk = atk;
                 }
             }
         }
     }
 
+    fn can_attack(&self, unit: &Unit, other: &Unit) -> bool {
+        self.scenario.hostile(unit.faction, other.faction)
+    }
+
+    fn unit(&self, p: Pos) -> &Unit {
+        self.grids[p].unit.as_ref().unwrap()
+    }
+
+    fn strategy(&self, f: Faction) -> Arc<dyn Strategy> {
+        self.strategies.get(f.0)
+            .cloned()
+            .unwrap_or_else(|| Arc::new(Puzzle))
+    }
+
+    fn set_strategy(&mut self, f: Faction, strategy: Arc<dyn Strategy>) {
+        if self.strategies.len() <= f.0 {
+            self.strategies.resize_with(f.0 + 1, || Arc::new(Puzzle));
+        }
+        self.strategies[f.0] = strategy;
+    }
+
     fn neighbors(&self, p: Pos) -> impl Iterator<Item=Pos> + '_ {
-        [(0, -1), (-1, 0), (1, 0), (0, 1)].iter()
-            .flat_map(move |off| p.offset(*off))
-            .filter(move |&p| self.grids.has_pos(p))
+        self.grids.neighbors(p)
     }
 
 
@@ -217,201 +530,670 @@ impl Cave {
         self.grids[to].unit = Some(unit);
     }
 
-    fn attack(&mut self, p: Pos, enemy_pos: Pos) {
-        let atk = self.grids[p]
-            .unit.as_ref().unwrap()
-            .atk;
+    fn attack(&mut self, p: Pos, enemy_pos: Pos, log: &mut Vec<Event>) {
+        let (id, atk) = {
+            let unit = self.grids[p].unit.as_ref().unwrap();
+            (unit.id, unit.atk)
+        };
+
+        let enemy = self.grids[enemy_pos].unit.as_mut().unwrap();
+        let target = enemy.id;
+        let damage = atk.min(enemy.hp);
+        log.push(Event::Attacked { unit: id, target, at: enemy_pos, damage });
 
-        let enemy = &mut self.grids[enemy_pos];
-        if enemy.unit.as_mut().unwrap().damage(atk) {
-            enemy.unit.take();
+        if enemy.damage(atk) {
+            self.grids[enemy_pos].unit.take();
+            log.push(Event::Died { unit: target, at: enemy_pos });
         }
     }
 
-
-    fn update(&mut self) {
+    ///Plays one round and logs its events. Returns false if it ended early
+    ///because a unit found no targets, that round does not count.
+    fn update(&mut self, log: &mut Vec<Event>) -> bool {
         let units: Vec<_> = self.grids.coords()
-            .map(|p| (p, &self.grids[p]))
-            .filter(|(_, g)| g.unit.is_some())
-            .map(|(p, g)| (p, g.unit.as_ref().unwrap().id))
+            .filter_map(|p| self.grids[p].unit.as_ref().map(|u| (p, u.id)))
             .collect();
 
-        for (p, _) in units {
-            //Check if unit just died
-            if self.grids[p].unit.is_none() {
-                continue
+        for (p, id) in units {
+            //Skip units that died, another unit may have moved there
+            let faction = match &self.grids[p].unit {
+                Some(u) if u.id == id => u.faction,
+                _ => continue
+            };
+
+            if !self.units().any(|u| self.scenario.hostile(faction, u.faction)) {
+                return false;
             }
 
-            //Find closest enemy
-            match self.find_close_enemy(p) {
-                Some(enemy_pos) => {
-                    self.attack(p, enemy_pos);
-                },
-                None => {
-                    if let Some(next_pos) = self.find_nearest_enemy(p) {
-                        self.move_unit(p, next_pos);
-
-                        if let Some(enemy_pos) = self.find_close_enemy(next_pos) {
-                            self.attack(next_pos, enemy_pos);
-                        }
-                    }
+            let strategy = self.strategy(faction);
+            let p = match strategy.step(self, p) {
+                Some(next) => {
+                    debug_assert!(self.neighbors(p).any(|n| n == next) && !self.grids[next].is_blocked());
+                    self.move_unit(p, next);
+                    log.push(Event::Moved { unit: id, from: p, to: next });
+                    next
                 }
+                None => p
+            };
+
+            if let Some(enemy_pos) = strategy.target(self, p) {
+                self.attack(p, enemy_pos, log);
             }
         }
+
+        self.round += 1;
+        log.push(Event::RoundCompleted { round: self.round });
+        true
     }
 
+    ///Adjacent enemy with the fewest hit points, ties in reading order
     fn find_close_enemy(&self, p: Pos) -> Option<Pos> {
-        let unit = self.grids[p]
-            .unit
-            .as_ref()
-            .unwrap();
+        let unit = self.unit(p);
 
         self.neighbors(p)
-            .filter_map(|p| {
-                match &self.grids[p] {
-                    Grid { unit: Some(other), .. } => {
-                        if unit.can_attack(&other) {
-                            Some((p, other.id, other.hp))
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None
-                }
+            .filter_map(|n| match &self.grids[n].unit {
+                Some(other) if self.can_attack(unit, other) => Some((n, other.hp)),
+                _ => None
             })
-            .min_by_key(|(_, _, hp)| *hp)
-            .map(|(t, _, _)| t)
+            .min_by_key(|&(n, hp)| (hp, reading(n)))
+            .map(|(n, _)| n)
     }
 
-    fn find_nearest_enemy(&mut self, p: Pos) -> Option<Pos> {
-        let mut search = Mat2::new(self.h, self.w);
-
-        let g = &self.grids[p];
-        let enemy = g.unit.as_ref().unwrap().opponent_kind();
+    ///First step towards the nearest reachable square in range of a unit matching `target`.
+    ///Ties between squares and between steps go to the first in reading order.
+    fn next_step<F: Fn(&Unit) -> bool>(&self, p: Pos, target: F) -> Option<Pos> {
+        let open = |g: &Grid| !g.is_blocked();
+        let dist = self.grids.flood_fill(p, open);
+
+        let target = self.grids.coords()
+            .filter(|&q| self.grids[q].unit.as_ref().is_some_and(&target))
+            .flat_map(|q| self.neighbors(q).collect::<Vec<_>>())
+            .filter(|&q| q != p)
+            .filter_map(|q| dist[q].map(|d| (q, d)))
+            .min_by_key(|&(q, d)| (d, reading(q)))
+            .map(|(q, _)| q)?;
+
+        let back = self.grids.flood_fill(target, open);
+        self.neighbors(p)
+            .filter_map(|n| back[n].map(|d| (n, d)))
+            .min_by_key(|&(n, d)| (d, reading(n)))
+            .map(|(n, _)| n)
+    }
 
-        let (mut min_id, mut pos, mut min_d) = (usize::MAX, Pos::new(0, 0), usize::MAX);
+    ///Full rounds and the surviving factions. Also stops at a stalemate, when
+    ///the units stand where they stood before without anyone getting hurt since.
+    fn run_to_end(&mut self, log: &mut Vec<Event>) -> (usize, Vec<Faction>) {
+        let mut seen = HashSet::new();
 
-        let mut q = VecDeque::new();
-        q.push_back((1, p));
-        search[p] = (p, 1);
+        loop {
+            let start = log.len();
+            if !self.update(log) {
+                break;
+            }
 
-        while let Some((d, p)) = q.pop_front() {
-            if d > min_d {
-                break
+            let hurt = log[start..].iter()
+                .any(|e| matches!(e, Event::Attacked { damage, .. } if *damage > 0));
+            if hurt {
+                seen.clear();
+            } else if !seen.insert(self.placement()) {
+                break;
             }
+        }
+
+        (self.round, self.survivors())
+    }
 
-            for n in self.neighbors(p) {
-                match &self.grids[n] {
-                    Grid { unit: Some(Unit { kind,  .. }), .. } if *kind == enemy => {
-                        let idx = n.x + self.h * n.y;
-                        if idx < min_id && d < min_d {
-                            search[n] = (p, d + 1);
-                            min_id = idx;
-                            min_d = d+1;
-                            pos = n;
-                        }
-                    }
-                    g if !g.is_blocked() && search[n].1 == 0 => {
-                        q.push_back((d+1, n));
-                        search[n] = (p, d + 1);
-                    }
-                    _ => {
-                        //Ignore neighbor If the field is blocked
-                    }
-                };
+    ///Ids of the units in reading order of their squares
+    fn placement(&self) -> Vec<(usize, usize, usize)> {
+        self.grids.coords()
+            .filter_map(|p| self.grids[p].unit.as_ref().map(|u| (p.y, p.x, u.id)))
+            .collect()
+    }
+
+    ///Applies a logged event, checking that it fits the current state
+    fn apply(&mut self, e: &Event) -> Result<()> {
+        let id_at = |cave: &Cave, p: Pos| cave.grids.get(p)
+            .and_then(|g| g.unit.as_ref())
+            .map(|u| u.id);
+
+        match *e {
+            Event::Moved { unit, from, to } => {
+                if id_at(self, from) != Some(unit) || self.grids.get(to).is_none_or(|g| g.is_blocked()) {
+                    return Err(format!("Unit {} cannot move from {:?} to {:?}", unit, from, to).into());
+                }
+                self.move_unit(from, to);
+            }
+            Event::Attacked { unit, target, at, damage } => {
+                if id_at(self, at) != Some(target) {
+                    return Err(format!("Unit {} attacks missing unit {} at {:?}", unit, target, at).into());
+                }
+                self.grids[at].unit.as_mut().unwrap().damage(damage);
+            }
+            Event::Died { unit, at } => {
+                let dead = self.grids[at].unit.as_ref().is_some_and(|u| u.id == unit && u.hp == 0);
+                if !dead {
+                    return Err(format!("Unit {} at {:?} is not dead", unit, at).into());
+                }
+                self.grids[at].unit.take();
+            }
+            Event::RoundCompleted { round } => {
+                if round != self.round + 1 {
+                    return Err(format!("Round {} completed after round {}", round, self.round).into());
+                }
+                self.round = round;
             }
         }
 
-        if min_id == usize::MAX {
-            return None;
+        Ok(())
+    }
+
+    ///Rounds, surviving factions and their hit points
+    fn outcome(&self) -> String {
+        let survivors: Vec<&str> = self.survivors().into_iter()
+            .map(|f| self.scenario.name(f))
+            .collect();
+        format!("rounds: {}, survivors: {}, hp: {}", self.round, survivors.join(" "), self.hp_sum())
+    }
+
+    ///Map with the hit points of the units of each row, as in the puzzle text
+    fn describe(&self) -> String {
+        let mut s = String::new();
+        for (y, row) in self.plain().lines().enumerate() {
+            let units: Vec<String> = (0..self.w)
+                .filter_map(|x| self.grids[Pos::new(x, y)].unit.as_ref())
+                .map(|u| format!("{}{}({})", u.glyph, u.id, u.hp))
+                .collect();
+
+            s.push_str(row);
+            if !units.is_empty() {
+                s.push_str("   ");
+                s.push_str(&units.join(", "));
+            }
+            s.push('\n');
         }
 
+        s
+    }
 
-        let mut last_pos = pos;
-        loop {
-            let (last, d) = search.get(last_pos).unwrap();
-            if *d == 2 {
-                return Some(last_pos);
+    ///Map with the glyphs of the units, as in the puzzle text
+    fn plain(&self) -> String {
+        let mut s = String::new();
+        for y in 0..self.h {
+            for x in 0..self.w {
+                s.push(self.grids[Pos::new(x, y)].to_char());
             }
-            last_pos = *last;
+            s.push('\n');
         }
+
+        s
     }
+}
 
-    fn run_to_end(&mut self) -> (usize, UnitKind) {
-        let mut r = 0;
+///One JSON object per line
+fn write_log<W: Write>(log: &[Event], mut out: W) -> Result<()> {
+    for e in log {
+        writeln!(out, "{}", serde_json::to_string(e)?)?;
+    }
 
-        while !self.is_done() {
-            self.update();
-            r +=1;
+    Ok(())
+}
+
+fn read_log(s: &str) -> Result<Vec<Event>> {
+    s.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| Ok(serde_json::from_str(l)?))
+        .collect()
+}
+
+///Battle from `initial` on, its event log and states
+fn fight(initial: &Cave) -> Result<(Vec<Event>, Vec<Cave>)> {
+    let mut log = Vec::new();
+    initial.clone().run_to_end(&mut log);
+    let states = replay(initial, &log)?;
+    Ok((log, states))
+}
+
+///Every state from `initial` on: the cave after each completed round,
+///and the final state if the battle ended during a round
+fn replay(initial: &Cave, log: &[Event]) -> Result<Vec<Cave>> {
+    let mut cave = initial.clone();
+    let mut states = vec![cave.clone()];
+    let mut pending = false;
+
+    for e in log {
+        cave.apply(e)?;
+        pending = true;
+
+        if let Event::RoundCompleted { .. } = e {
+            states.push(cave.clone());
+            pending = false;
         }
+    }
 
-        (r, self.winner().unwrap())
+    if pending {
+        states.push(cave);
     }
+
+    Ok(states)
+}
+
+///Events of round `n`, counting from 1
+fn round_events(log: &[Event], n: usize) -> &[Event] {
+    let ends: Vec<usize> = log.iter()
+        .enumerate()
+        .filter(|(_, e)| matches!(e, Event::RoundCompleted { .. }))
+        .map(|(ix, _)| ix + 1)
+        .collect();
+
+    let start = if n <= 1 { 0 } else { ends.get(n - 2).cloned().unwrap_or(log.len()) };
+    let end = ends.get(n - 1).cloned().unwrap_or(log.len());
+    &log[start.min(end)..end]
+}
+
+fn reading(p: Pos) -> (usize, usize) {
+    (p.y, p.x)
 }
 
 impl FromStr for Cave {
     type Err = Box<std::error::Error>;
 
     fn from_str(s: &str) -> Result<Self> {
-        let h = s.lines().count();
-        let w = s.lines().next().unwrap().len();
+        Cave::parse_with(s, Scenario::default())
+    }
+}
 
-        let mut cave = Cave::new(h, w);
-        let mut id = 1;
-        for (y, l) in s.lines().enumerate() {
-            for (x, c) in l.chars().enumerate() {
-                let grid = Grid::from_char(c, id)?;
-                if grid.unit.is_some() {
-                    id += 1;
-                }
 
-                cave.grids[Pos::new(x, y)] = grid;
+impl Animate for Cave {
+    fn frame(&self) -> Mat2<char> {
+        self.grids.map(|g| g.to_char())
+    }
+
+    fn step(&mut self) -> bool {
+        if self.is_done() {
+            return false;
+        }
+
+        self.update(&mut Vec::new());
+        true
+    }
+}
+
+///Battle of the debugger, with its event log and the states rebuilt from it
+struct Inspector<'a> {
+    ///Input map, scenarios are placed on it by default
+    map: &'a str,
+    initial: Cave,
+    log: Vec<Event>,
+    states: Vec<Cave>
+}
+
+impl<'a> Inspector<'a> {
+    fn new(map: &'a str, initial: Cave) -> Result<Inspector<'a>> {
+        let (log, states) = fight(&initial)?;
+        Ok(Inspector { map, initial, log, states })
+    }
+
+    ///Fights from a new initial state
+    fn refight<W: Write>(&mut self, initial: Cave, out: W) -> Result<()> {
+        *self = Inspector::new(self.map, initial)?;
+        self.summary(out)
+    }
+
+    fn summary<W: Write>(&self, mut out: W) -> Result<()> {
+        writeln!(out, "{} states, {}", self.states.len(), self.states.last().unwrap().outcome())?;
+        Ok(())
+    }
+
+    fn faction(&self, name: &str) -> Result<Faction> {
+        self.initial.scenario.faction(name)
+            .ok_or_else(|| format!("Unknown faction {}", name).into())
+    }
+
+    fn round<W: Write>(&self, n: usize, mut out: W) -> Result<()> {
+        let cave = self.states.get(n).ok_or("No such round")?;
+        write!(out, "{}", cave.describe())?;
+        Ok(())
+    }
+
+    fn events<W: Write>(&self, n: usize, out: W) -> Result<()> {
+        write_log(round_events(&self.log, n), out)
+    }
+
+    fn save(&self, path: &str) -> Result<()> {
+        write_log(&self.log, File::create(path)?)
+    }
+
+    ///Replays a log onto the initial state
+    fn load<W: Write>(&mut self, path: &str, mut out: W) -> Result<()> {
+        let log = read_log(&load(path)?)?;
+        self.states = replay(&self.initial, &log)?;
+        self.log = log;
+        writeln!(out, "{} states", self.states.len())?;
+        Ok(())
+    }
+
+    fn sweep<W: Write>(&self, k: Option<usize>, mut out: W) -> Result<()> {
+        let elf = self.faction("elf")?;
+        let table = sweep(&self.initial, elf, attack_range(&self.initial, elf));
+        print_table(&self.initial, &table, &mut out)?;
+
+        if let Some(k) = k {
+            match min_attack(&table, elf, k) {
+                Some(t) => writeln!(out, "atk {}", t.atk)?,
+                None => writeln!(out, "elves do not win with at most {} deaths", k)?
             }
         }
 
-        Ok(cave)
+        Ok(())
+    }
+
+    fn scenario<W: Write>(&mut self, path: &str, map: Option<&str>, out: W) -> Result<()> {
+        let scenario = Scenario::load(path)?;
+        let cave = match map {
+            Some(map) => Cave::parse_with(&load(map)?, scenario)?,
+            None => Cave::parse_with(self.map, scenario)?
+        };
+        self.refight(cave, out)
+    }
+
+    fn strategy<W: Write>(&mut self, faction: &str, spec: &str, out: W) -> Result<()> {
+        let f = self.faction(faction)?;
+        let spec: StrategySpec = spec.parse()?;
+
+        let mut cave = self.initial.clone();
+        cave.set_strategy(f, spec.build());
+        self.refight(cave, out)
+    }
+
+    fn compare<W: Write>(&self, faction: &str, out: W) -> Result<()> {
+        print_comparison(&self.initial, self.faction(faction)?, out)
     }
 }
 
+const REPLAY_HELP: &str = "\
+r, round <n>     map and hit points after round n, 0 is the start
+e, events <n>    events of round n as JSON Lines
+save <file>      write the event log as JSON Lines
+load <file>      replay an event log onto the input map
+strategy <faction> <strategy>
+                 fight again with puzzle, focus_fire, retreat <hp> or hold
+                 as the strategy of a faction
+compare <faction>
+                 outcomes of the built-in strategies for a faction
+sweep [k]        battles for every elf attack, and the lowest one
+                 that wins with at most k elf deaths
+scenario <file> [map]
+                 fight again with the units of a JSON scenario, on the input map
+                 or another one
+q, quit          exit";
 
 #[derive(Default)]
 pub struct Solution15 {
+    map: String,
     data: Cave,
+    ///Input map with the units of the scenario file, if there is one
+    what_if: Option<Cave>,
     sample: Cave
 }
+///Worked battles of the puzzle text: map, full rounds, remaining hit points
+const BATTLES: [(&str, usize, u64); 6] = [
+    ("#######\n#.G...#\n#...EG#\n#.#.#G#\n#..G#E#\n#.....#\n#######\n", 47, 590),
+    ("#######\n#G..#E#\n#E#E.E#\n#G.##.#\n#...#E#\n#...E.#\n#######\n", 37, 982),
+    ("#######\n#E..EG#\n#.#G.E#\n#E.##E#\n#G..#.#\n#..E#.#\n#######\n", 46, 859),
+    ("#######\n#E.G#.#\n#.#G..#\n#G.#.G#\n#G..#.#\n#...E.#\n#######\n", 35, 793),
+    ("#######\n#.E...#\n#.#..G#\n#.###.#\n#E#G#G#\n#...#G#\n#######\n", 54, 536),
+    ("#########\n#G......#\n#.E.#...#\n#..##..G#\n#...##..#\n#...#...#\n#.G...G.#\n#.....G.#\n#########\n", 20, 937),
+];
+
+///Elf attack, full rounds and remaining hit points of the worked battles
+///without elf deaths, indices into `BATTLES`
+const FLAWLESS: [(usize, u64, usize, u64); 5] = [
+    (0, 15, 29, 172),
+    (2, 4, 33, 948),
+    (3, 15, 37, 94),
+    (4, 12, 39, 166),
+    (5, 34, 30, 38),
+];
+
+///Movement example of the puzzle text, the map after 0 to 3 rounds
+const MOVEMENT: [&str; 4] = [
+    "#########\n#G..G..G#\n#.......#\n#.......#\n#G..E..G#\n#.......#\n#.......#\n#G..G..G#\n#########\n",
+    "#########\n#.G...G.#\n#...G...#\n#...E..G#\n#.G.....#\n#.......#\n#G..G..G#\n#.......#\n#########\n",
+    "#########\n#..G.G..#\n#...G...#\n#.G.E.G.#\n#.......#\n#G..G..G#\n#.......#\n#.......#\n#########\n",
+    "#########\n#.......#\n#..GGG..#\n#..GEG..#\n#G..G...#\n#......G#\n#.......#\n#.......#\n#########\n",
+];
+
+///Full rounds and remaining hit points
+fn battle(g: &mut Cave) -> (usize, u64) {
+    let (r, _) = g.run_to_end(&mut Vec::new());
+    (r, g.hp_sum())
+}
+
+///Battle fought with the attack of a faction raised to `atk`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Trial {
+    atk: u64,
+    survivors: Vec<Faction>,
+    rounds: usize,
+    ///Units of the faction that died
+    deaths: usize,
+    hp: u64
+}
+
+fn trial(g: &Cave, faction: Faction, atk: u64) -> Trial {
+    let mut g = g.clone();
+    let units = g.count(faction);
+    g.set_attack(faction, atk);
+    let (rounds, survivors) = g.run_to_end(&mut Vec::new());
+
+    Trial {
+        atk,
+        deaths: units - g.count(faction),
+        survivors,
+        rounds,
+        hp: g.hp_sum()
+    }
+}
+
+///Attacks of `faction` worth trying: from its current one up to the one that
+///kills any enemy with a single hit, higher attacks change nothing
+fn attack_range(g: &Cave, faction: Faction) -> RangeInclusive<u64> {
+    let lo = g.units()
+        .filter(|u| u.faction == faction)
+        .map(|u| u.atk)
+        .min()
+        .unwrap_or(1)
+        .max(1);
+    let hi = g.units()
+        .filter(|u| g.scenario.hostile(faction, u.faction))
+        .map(|u| u.hp)
+        .max()
+        .unwrap_or(lo);
+
+    lo..=hi.max(lo)
+}
+
+fn workers() -> u64 {
+    std::thread::available_parallelism().map_or(1, |n| n.get()) as u64
+}
+
+///Battles for the attacks `atks` of `faction`, spread over all cores.
+///The table is ordered by attack.
+fn sweep(g: &Cave, faction: Faction, atks: RangeInclusive<u64>) -> Vec<Trial> {
+    let workers = workers();
+    let (lo, hi) = (*atks.start(), *atks.end());
+
+    let mut table: Vec<Trial> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|w| s.spawn(move || {
+                (lo + w..=hi).step_by(workers as usize)
+                    .map(|atk| trial(g, faction, atk))
+                    .collect::<Vec<_>>()
+            }))
+            .collect();
+
+        handles.into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+
+    table.sort_by_key(|t| t.atk);
+    table
+}
+
+///Sweeps a batch of attacks per core at a time, until `faction` wins without
+///deaths. That prefix of the table answers `min_attack` for any `k`.
+fn sweep_flawless(g: &Cave, faction: Faction) -> Vec<Trial> {
+    let atks = attack_range(g, faction);
+    let mut table = Vec::new();
+    let mut lo = *atks.start();
+
+    while lo <= *atks.end() && min_attack(&table, faction, 0).is_none() {
+        let hi = (lo + workers() - 1).min(*atks.end());
+        table.extend(sweep(g, faction, lo..=hi));
+        lo = hi + 1;
+    }
+
+    table
+}
+
+///Battles with each built-in strategy for `faction`, fought in parallel
+fn compare(g: &Cave, faction: Faction) -> Vec<(StrategySpec, Cave)> {
+    let hp = g.scenario.factions[faction.0].hp;
+
+    std::thread::scope(|s| {
+        let handles: Vec<_> = StrategySpec::all(hp).into_iter()
+            .map(|spec| s.spawn(move || {
+                let mut g = g.clone();
+                g.set_strategy(faction, spec.build());
+                g.run_to_end(&mut Vec::new());
+                (spec, g)
+            }))
+            .collect();
+
+        handles.into_iter()
+            .map(|h| h.join().unwrap())
+            .collect()
+    })
+}
+
+fn print_comparison<W: Write>(g: &Cave, faction: Faction, mut out: W) -> Result<()> {
+    let name = g.scenario.name(faction);
+    for (spec, c) in compare(g, faction) {
+        let deaths = g.count(faction) - c.count(faction);
+        writeln!(out, "{:>12}  {}, {} deaths: {}", spec.to_string(), c.outcome(), name, deaths)?;
+    }
+
+    Ok(())
+}
+
+///Lowest attack at which `faction` wins with at most `k` deaths
+fn min_attack(table: &[Trial], faction: Faction, k: usize) -> Option<&Trial> {
+    table.iter()
+        .find(|t| t.survivors == [faction] && t.deaths <= k)
+}
+
+fn print_table<W: Write>(g: &Cave, table: &[Trial], mut out: W) -> Result<()> {
+    writeln!(out, "{:>5} {:>10} {:>7} {:>7} {:>6}", "atk", "winner", "rounds", "deaths", "hp")?;
+    for t in table {
+        let winner: Vec<&str> = t.survivors.iter()
+            .map(|&f| g.scenario.name(f))
+            .collect();
+        writeln!(out, "{:>5} {:>10} {:>7} {:>7} {:>6}", t.atk, winner.join(" "), t.rounds, t.deaths, t.hp)?;
+    }
+
+    Ok(())
+}
+
+///Battle of the lowest elf attack without elf deaths in a table of `g`
+fn flawless_trial<'a>(g: &Cave, table: &'a [Trial]) -> Result<&'a Trial> {
+    min_attack(table, ELF, 0).ok_or_else(|| {
+        let atks = attack_range(g, ELF);
+        format!("No elf attack from {} to {} wins without losses", atks.start(), atks.end()).into()
+    })
+}
+
+///Lowest elf attack without elf deaths, with the rounds and hit points of that battle
+fn flawless(g: &Cave) -> Result<(u64, usize, u64)> {
+    let table = sweep_flawless(g, ELF);
+    let t = flawless_trial(g, &table)?;
+    Ok((t.atk, t.rounds, t.hp))
+}
+
+fn check_examples() -> Result<()> {
+    let mut cave: Cave = MOVEMENT[0].parse()?;
+    for frame in MOVEMENT.iter().skip(1) {
+        cave.update(&mut Vec::new());
+        assert_eq!(*frame, cave.plain());
+    }
+
+    for (map, rounds, hp) in BATTLES.iter() {
+        let mut cave: Cave = map.parse()?;
+        assert_eq!((*rounds, *hp), battle(&mut cave));
+    }
+
+    for &(ix, atk, rounds, hp) in FLAWLESS.iter() {
+        let cave: Cave = BATTLES[ix].0.parse()?;
+        assert_eq!((atk, rounds, hp), flawless(&cave)?);
+    }
+
+    Ok(())
+}
+
 fn part1(g: &mut Cave) -> u64 {
-    let (r, _) = g.run_to_end();
+    let (r, hp) = battle(g);
 
     for u in g.units() {
         println!("unit {:?}", u);
     }
-    let hp: u64 = g.hp_sum();
     println!("rounds: {}, hp: {}", r, hp);
     (r as u64) * hp
 }
 
-fn part2(g: &mut Cave) -> u64 {
-    let elfs = g.elfs();
-    for i in 4.. {
-        let mut g = g.clone();
-        g.upgrade_elf_weapons(i);
-        let (r, winner) = g.run_to_end();
-
-        if winner == UnitKind::Elf && elfs == g.elfs() {
-            println!("atk: {}, e: {}, ee: {}", i, elfs, g.elfs());
-            let hp: u64 = g.hp_sum();
-            return ((r as u64) - 1) * hp;
+fn part2(g: &mut Cave) -> Result<u64> {
+    let table = sweep_flawless(g, ELF);
+    for k in 1..g.count(ELF) {
+        if let Some(t) = min_attack(&table, ELF, k) {
+            println!("at most {} elf deaths: atk {}", k, t.atk);
         }
     }
 
-    0
+    let t = flawless_trial(g, &table)?;
+    println!("atk: {}, rounds: {}, hp: {}", t.atk, t.rounds, t.hp);
+    Ok((t.rounds as u64) * t.hp)
+}
+
+impl Solution15 {
+    ///Battle of the scenario and how each faction fares with the built-in strategies
+    fn what_if(&self) -> Result<()> {
+        let cave = match &self.what_if {
+            Some(cave) => cave,
+            None => return Ok(())
+        };
+
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        let mut end = cave.clone();
+        end.run_to_end(&mut Vec::new());
+        writeln!(out, "scenario: {}", end.outcome())?;
 
+        for f in 0..cave.scenario.factions.len() {
+            print_comparison(cave, Faction(f), &mut out)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Solution for Solution15 {
     fn init(&mut self) -> Result<()> {
-        let s = load(&data_path(15))?;
-        self.data = s.parse()?;
+        self.map = load(&data_path(15))?;
+        self.data = self.map.parse()?;
+
+        let path = scenario_path(15);
+        if Path::new(&path).exists() {
+            self.what_if = Some(Cave::parse_with(&self.map, Scenario::load(&path)?)?);
+        }
 
         let s = load(&sample_path(15))?;
         self.sample = s.parse()?;
@@ -420,6 +1202,8 @@ impl Solution for Solution15 {
     }
 
     fn part1(&mut self) -> Result<()> {
+        check_examples()?;
+
         let sample1 = part1(&mut self.sample.clone());
         println!("s1: {}", sample1);
 
@@ -430,11 +1214,63 @@ impl Solution for Solution15 {
     }
 
     fn part2(&mut self) -> Result<()> {
-        let sample1 = part2(&mut self.sample.clone());
+        let sample1 = part2(&mut self.sample.clone())?;
         println!("s2: {}", sample1);
 
-        let result1 = part2(&mut self.data.clone());
+        let result1 = part2(&mut self.data.clone())?;
         println!("result2: {}", result1);
         Ok(())
     }
+
+    fn animate(&mut self) -> Result<()> {
+        Player::default().play(&mut self.data.clone())
+    }
+
+    ///Also fights the scenario of `scenario_path`, if the file exists
+    fn run(&mut self) -> Result<()> {
+        self.init()?;
+        self.part1()?;
+        self.part2()?;
+        self.what_if()
+    }
+
+    ///Inspects the rounds of the battle, rebuilt from its event log
+    fn debug(&mut self) -> Result<()> {
+        let mut inspector = Inspector::new(&self.map, self.data.clone())?;
+
+        let stdin = std::io::stdin();
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        writeln!(out, "{} states, try help", inspector.states.len())?;
+
+        for line in stdin.lock().lines() {
+            let line = line?;
+            let args: Vec<&str> = line.split_whitespace().collect();
+
+            let result = match args[..] {
+                ["r", n] | ["round", n] => n.parse::<usize>().map_err(|e| e.into())
+                    .and_then(|n| inspector.round(n, &mut out)),
+                ["e", n] | ["events", n] => n.parse::<usize>().map_err(|e| e.into())
+                    .and_then(|n| inspector.events(n, &mut out)),
+                ["save", path] => inspector.save(path),
+                ["load", path] => inspector.load(path, &mut out),
+                ["sweep"] => inspector.sweep(None, &mut out),
+                ["sweep", k] => k.parse::<usize>().map_err(|e| e.into())
+                    .and_then(|k| inspector.sweep(Some(k), &mut out)),
+                ["scenario", path] => inspector.scenario(path, None, &mut out),
+                ["scenario", path, map] => inspector.scenario(path, Some(map), &mut out),
+                ["strategy", faction, ..] => inspector.strategy(faction, &args[2..].join(" "), &mut out),
+                ["compare", faction] => inspector.compare(faction, &mut out),
+                ["q"] | ["quit"] => break,
+                [] => Ok(()),
+                _ => Ok(writeln!(out, "{}", REPLAY_HELP)?)
+            };
+
+            if let Err(e) = result {
+                writeln!(out, "error: {}", e)?;
+            }
+        }
+
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -4,9 +4,19 @@ use util::file::data_path;
 use util::file::load;
 
 use regex::Regex;
-use std::collections::HashSet;
+use device::Cpu;
+use device::Debugger;
+use device::fault::Overflow;
+use device::deduce::deduce;
+use device::deduce::Deduction;
+use device::deduce::Options;
+use device::Instruction;
+use device::Opcode;
+use device::Program;
+use device::program::parse_instruction;
+use std::fmt::Display;
+use std::fmt::Formatter;
 
-type Instruction = [u64; 4];
 type Capture = [u64; 4];
 
 struct Sample {
@@ -21,239 +31,28 @@ pub struct Solution16 {
     prog: Vec<Instruction>,
 }
 
-pub struct Cpu {
-    register: [u64; 4]
-}
-
-#[derive(Copy, Clone, Debug)]
-enum Reg {
-    Reg0,
-    Reg1,
-    Reg2,
-    Reg3,
-}
-
-#[derive(Debug)]
-enum Opcode {
-    Addr { a: Reg, b: Reg, c: Reg },
-    Addi { a: Reg, b: u64, c: Reg },
-
-    Mulr { a: Reg, b: Reg, c: Reg },
-    Muli { a: Reg, b: u64, c: Reg },
-
-    Banr { a: Reg, b: Reg, c: Reg },
-    Bani { a: Reg, b: u64, c: Reg },
-
-    Borr { a: Reg, b: Reg, c: Reg },
-    Bori { a: Reg, b: u64, c: Reg },
-
-    Setr { a: Reg, c: Reg },
-    Seti { a: u64, c: Reg },
-
-    Gtir { a: u64, b: Reg, c: Reg },
-    Gtri { a: Reg, b: u64, c: Reg },
-    Gtrr { a: Reg, b: Reg, c: Reg },
-
-    Eqir { a: u64, b: Reg, c: Reg },
-    Eqri { a: Reg, b: u64, c: Reg },
-    Eqrr { a: Reg, b: Reg, c: Reg },
-}
-
-impl Reg {
-    fn to_index(&self) -> usize {
-        use self::Reg::*;
-        match self {
-            Reg0 => 0,
-            Reg1 => 1,
-            Reg2 => 2,
-            Reg3 => 3
-        }
-    }
-
-    fn from_index(ix: usize) -> Result<Reg> {
-        use self::Reg::*;
-        let reg = match ix {
-            0 => Reg0,
-            1 => Reg1,
-            2 => Reg2,
-            3 => Reg3,
-            _ => return Err("Invalid index".into())
-        };
-
-        Ok(reg)
-    }
-}
-
-impl Cpu {
-    fn new() -> Cpu {
-        Cpu { register: [0; 4] }
-    }
-    fn from_capture(cap: Capture) -> Cpu {
-        Cpu { register: cap }
-    }
-
-    fn capture(&self) -> Capture {
-        self.register.clone()
-    }
-
-    fn get_reg(&self, r: Reg) -> u64 {
-        self.register[r.to_index()]
-    }
-
-    fn set_reg(&mut self, r: Reg, val: u64) {
-        self.register[r.to_index()] = val
-    }
-}
-
-impl Opcode {
-    fn exec(&self, cpu: &mut Cpu) -> Result<()> {
-        use self::Opcode::*;
-        match *self {
-            Addr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a + b);
-            }
-            Addi { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a + b);
-            }
-
-            Mulr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a * b);
-            }
-            Muli { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a * b);
-            }
-
-            Borr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a | b);
-            }
-            Bori { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a | b);
-            }
-
-            Banr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a & b);
-            }
-            Bani { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a & b);
-            }
-
-            Setr { a, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a);
-            }
-            Seti { a, c } => {
-                cpu.set_reg(c, a);
-            }
-
-            Gtir { a, b, c } => {
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a > b) as u64);
-            }
-            Gtri { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, (a > b) as u64);
-            }
-            Gtrr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a > b) as u64);
-            }
-
-            Eqir { a, b, c } => {
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a == b) as u64);
-            }
-            Eqri { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, (a == b) as u64);
-            }
-            Eqrr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a == b) as u64);
-            }
-        }
-
-        Ok(())
-    }
+const REGS: usize = 4;
+///Mappings listed if the samples do not pin the opcodes down
+const MAX_MAPPINGS: usize = 10;
 
-    fn from_instr(instr: Instruction) -> Result<Opcode> {
-        use self::Opcode::*;
-        let (op, a, b, c) = (instr[0], instr[1], instr[2], instr[3]);
-        let reg_a = Reg::from_index(a as usize);
-        let reg_b = Reg::from_index(b as usize);
-        let reg_c = Reg::from_index(c as usize);
-
-        let op = match op {
-            0 => Addr { a: reg_a?, b: reg_b?, c: reg_c? },
-            1 => Addi { a: reg_a?, b, c: reg_c? },
-
-            2 => Mulr { a: reg_a?, b: reg_b?, c: reg_c? },
-            3 => Muli { a: reg_a?, b, c: reg_c? },
-
-            4 => Banr { a: reg_a?, b: reg_b?, c: reg_c? },
-            5 => Bani { a: reg_a?, b, c: reg_c? },
-
-            6 => Borr { a: reg_a?, b: reg_b?, c: reg_c? },
-            7 => Bori { a: reg_a?, b, c: reg_c? },
-
-            8 => Setr { a: reg_a?, c: reg_c? },
-            9 => Seti { a, c: reg_c? },
-
-            10 => Gtir { a, b: reg_b?, c: reg_c? },
-            11 => Gtri { a: reg_a?, b, c: reg_c? },
-            12 => Gtrr { a: reg_a?, b: reg_b?, c: reg_c? },
-
-            13 => Eqir { a, b: reg_b?, c: reg_c? },
-            14 => Eqri { a: reg_a?, b, c: reg_c? },
-            15 => Eqrr { a: reg_a?, b: reg_b?, c: reg_c? },
-            _ => return Err("Invalid opcode".into())
-        };
-
-        Ok(op)
-    }
-
-    fn count() -> usize {
-        16
-    }
-}
-
-
-
-fn op_options(samples: &[Sample]) -> Vec<(u64, HashSet<u64>)> {
+fn op_options(samples: &[Sample]) -> Vec<Options> {
     let mut result = Vec::new();
     let n = Opcode::count();
 
     for (_, s) in samples.iter().enumerate() {
-        let op = s.instr[0];
+        let op = s.instr[0] as usize;
 
         let v = (0..n)
-            .filter_map(|i| {
-                let mut cpu  = Cpu::from_capture(s.before);
-                let mut instr = s.instr;
-                instr[0] = i as u64;
-
-                if let Ok(op) = Opcode::from_instr(instr) {
-                    op.exec(&mut cpu).unwrap();
-
-                    if cpu.capture() == s.after {
-                        return Some(instr[0]);
-                    }
+            .filter(|&i| {
+                let mut cpu = Cpu::from_registers(&s.before);
+                let [_, a, b, c] = s.instr;
+
+                //Opcodes that fault do not match
+                match Opcode::from_index(i, a, b, c, REGS) {
+                    Ok(op) => op.exec(&mut cpu, Overflow::Checked).is_ok()
+                        && cpu.registers() == s.after,
+                    Err(_) => false
                 }
-
-                None
             })
             .collect();
 
@@ -270,53 +69,40 @@ fn part1(samples: &[Sample]) -> Result<usize> {
     Ok(count)
 }
 
-fn part2(samples: &[Sample], p: &[Instruction]) -> Result<u64> {
-    let mut t = [None; 16];
-    let mut opts = op_options(&samples);
-
-    while t.iter().filter(|o| o.is_none()).count() > 0 {
-        for i in 0..opts.len() {
-            if opts[i].1.len() != 1 {
-                continue;
-            }
-
-            let real_op = {
-                let can = &mut opts[i].1;
-                let last = *can.iter().next().unwrap();
-                can.remove(&last);
-                last
-            };
-            let op = opts[i].0;
-            t[op as usize] = Some(real_op);
-
-            for i in 0..opts.len() {
-                if opts[i].0 ==  op {
-                    opts[i].1.clear();
-                } else {
-                    opts[i].1.remove(&real_op);
-                }
-            }
+///Program with the opcode numbers replaced by the deduced opcodes
+fn deduced_program(samples: &[Sample], p: &[Instruction]) -> Result<Program> {
+    let opts = op_options(samples);
+
+    let table = match deduce(&opts, Opcode::count(), MAX_MAPPINGS) {
+        Deduction::Unique(table) => table,
+        Deduction::Conflict { numbers, samples: conflicting } => {
+            let lines: Vec<String> = conflicting.iter()
+                .map(|&ix| format!("sample {}: {}", ix, samples[ix]))
+                .collect();
+            return Err(format!("No consistent mapping for opcode numbers {:?}:\n{}",
+                               numbers, lines.join("\n")).into());
         }
-    }
+        d => return Err(d.to_string().into())
+    };
 
-    //run program
-    let prog: Vec<Opcode> = p.iter()
-        .map(|i| {
-            let mut instr = *i;
-            instr[0] = t[instr[0] as usize].unwrap();
-            Opcode::from_instr(instr).unwrap()
-        })
-        .collect();
-
-    let mut cpu = Cpu::new();
-    for op in prog.iter() {
-        op.exec(&mut cpu).unwrap();
-    }
+    Program::from_instructions(p, &table, REGS)
+}
+
+fn part2(samples: &[Sample], p: &[Instruction]) -> Result<u64> {
+    let prog = deduced_program(samples, p)?;
 
-    Ok(cpu.capture()[0])
+    let (cpu, outcome) = prog.run(None);
+    outcome.check()?;
+    Ok(cpu.registers()[0])
 }
 
 
+impl Display for Sample {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Before: {:?} {:?} After: {:?}", self.before, self.instr, self.after)
+    }
+}
+
 impl Solution for Solution16 {
     fn init(&mut self) -> Result<()> {
         let s = load(&data_path(16))?;
@@ -341,19 +127,15 @@ impl Solution for Solution16 {
         println!("result2: {}", result);
         Ok(())
     }
-}
-
-fn parse_instruction(s: &str) -> Result<Instruction> {
-    let mut instr = [0; 4];
-    for (i, s) in s.split_whitespace().enumerate() {
-        if i >= 4 {
-            return Err("Invalid amount of numbers in instruction".into());
-        }
 
-        instr[i] = s.parse()?;
+    ///Debugs the deduced program, `export` writes it as mnemonic text
+    fn debug(&mut self) -> Result<()> {
+        let prog = deduced_program(&self.samples, &self.prog)?;
+        let stdin = std::io::stdin();
+        let stdout = std::io::stdout();
+        Debugger::new(&prog, prog.cpu())
+            .repl(stdin.lock(), stdout.lock())
     }
-
-    Ok(instr)
 }
 
 fn parse_capture(s: &str) -> Result<Capture> {
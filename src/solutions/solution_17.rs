@@ -12,6 +12,8 @@ use util::mat2::Pos;
 use util::file::load;
 use util::file::sample_path;
 use std::collections::VecDeque;
+use util::player::Animate;
+use util::player::Player;
 
 struct ClaySquare {
     x: RangeInclusive<usize>,
@@ -58,6 +60,16 @@ impl TileKind {
             _ => false
         }
     }
+
+    fn to_char(self) -> char {
+        match self {
+            TileKind::RestingWater => '~',
+            TileKind::Sand => '.',
+            TileKind::Clay => '#',
+            TileKind::Spring => '+',
+            TileKind::FlowingWater => '|'
+        }
+    }
 }
 
 impl Default for TileKind {
@@ -66,7 +78,7 @@ impl Default for TileKind {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct Data {
     tiles: Mat2<TileKind>,
     source_x: usize
@@ -154,29 +166,17 @@ impl FromStr for Data {
 
 impl Display for TileKind {
     fn fmt(&self, f: &mut Formatter) -> std::result::Result<(), std::fmt::Error> {
-        let c = match self {
-            TileKind::RestingWater => '~',
-            TileKind::Sand => '.',
-            TileKind::Clay => '#',
-            TileKind::Spring => '+',
-            TileKind::FlowingWater => '|'
-        };
-
-        write!(f, "{}", c)
+        write!(f, "{}", self.to_char())
     }
 }
 
 impl Data {
     fn water_tiles(&self) -> usize {
-        self.tiles.grids()
-            .filter(|t| t.is_water())
-            .count()
+        self.tiles.count_where(|t| t.is_water())
     }
 
     fn resting_water_tiles(&self) -> usize {
-        self.tiles.grids()
-            .filter(|&&t| t == TileKind::RestingWater)
-            .count()
+        self.tiles.count_where(|&t| t == TileKind::RestingWater)
     }
 
     fn find_floor_bound(&self, p: Pos, dir: Direction) -> Option<Pos> {
@@ -253,16 +253,21 @@ impl Data {
     }
 
     fn update(&mut self) {
-        let h = self.tiles.height();
         let mut q = VecDeque::new();
-
         q.push_back(Pos::new(self.source_x, 0));
 
-        while let Some(p) = q.pop_front() {
-            if p.y == h {
-                continue;
+        while self.update_step(&mut q) {}
+    }
+
+    fn update_step(&mut self, q: &mut VecDeque<Pos>) -> bool {
+        match q.pop_front() {
+            Some(p) => {
+                if p.y != self.tiles.height() {
+                    self.update_flow(p, q);
+                }
+                true
             }
-            self.update_flow(p, &mut q);
+            None => false
         }
     }
 
@@ -291,6 +296,33 @@ impl Data {
 
 
 
+struct FlowAnimation {
+    data: Data,
+    q: VecDeque<Pos>
+}
+
+impl FlowAnimation {
+    fn new(data: Data) -> FlowAnimation {
+        let mut q = VecDeque::new();
+        q.push_back(Pos::new(data.source_x, 0));
+
+        FlowAnimation {
+            data,
+            q
+        }
+    }
+}
+
+impl Animate for FlowAnimation {
+    fn frame(&self) -> Mat2<char> {
+        self.data.tiles.map(|t| t.to_char())
+    }
+
+    fn step(&mut self) -> bool {
+        self.data.update_step(&mut self.q)
+    }
+}
+
 #[derive(Default)]
 pub struct Solution17 {
     data: Data,
@@ -326,4 +358,9 @@ impl Solution for Solution17 {
         println!("result2: {}", self.data.resting_water_tiles());
         Ok(())
     }
+
+    fn animate(&mut self) -> Result<()> {
+        let mut flow = FlowAnimation::new(self.data.clone());
+        Player::default().play(&mut flow)
+    }
 }
\ No newline at end of file
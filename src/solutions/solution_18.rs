@@ -8,8 +8,12 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use util::mat2::Mat2;
 use util::mat2::Pos;
+use util::player::Animate;
+use util::player::Player;
+use util::summed_area::SummedAreaTable;
+use util::cycle::fast_forward_by_key;
 
-#[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq, Hash)]
 pub enum Tile {
     Lumberyard,
     OpenGround,
@@ -83,44 +87,37 @@ impl FromStr for Data {
 }
 
 impl Data {
-    fn adjacent(&self, p: Pos) -> impl Iterator<Item=Pos> + '_ {
-        [(-1, -1), (0, -1), (1, -1),
-            (-1, 0), (1, 0),
-            (-1, 1), (0, 1), (1, 1)].iter()
-            .filter_map(move |&o| p.offset(o))
-            .filter( move |&p| self.tiles.has_pos(p))
-    }
-
     fn count_resources(&self) -> (usize, usize, usize) {
-        let (mut trees, mut lumberyards, mut open) = (0, 0, 0);
-        for p in self.tiles.coords() {
-            match self.tiles[p] {
-                Tile::OpenGround => open += 1,
-                Tile::Tree =>  trees += 1,
-                Tile::Lumberyard => lumberyards += 1
-            }
-        }
+        let trees = self.tiles.count_where(|&t| t == Tile::Tree);
+        let lumberyards = self.tiles.count_where(|&t| t == Tile::Lumberyard);
+        let open = self.tiles.count_where(|&t| t == Tile::OpenGround);
 
         (trees, lumberyards, open)
     }
 
-    fn count_adjacent(&self, p: Pos) -> (usize, usize, usize) {
-        let (mut trees, mut lumberyards, mut open) = (0, 0, 0);
-        for p in self.adjacent(p) {
-            match self.tiles[p] {
-                Tile::OpenGround => open += 1,
-                Tile::Tree =>  trees += 1,
-                Tile::Lumberyard => lumberyards += 1
-            }
-        }
+    fn resource_table(&self, kind: Tile) -> SummedAreaTable<usize> {
+        let m = self.tiles.map(|&t| (t == kind) as usize);
+        SummedAreaTable::new(&m)
+    }
 
-        (trees, lumberyards, open)
+    fn count_adjacent(table: &SummedAreaTable<usize>, p: Pos, t: Tile, kind: Tile) -> usize {
+        let origin = Pos::new(p.x.saturating_sub(1), p.y.saturating_sub(1));
+        let h = p.y + 2 - origin.y;
+        let w = p.x + 2 - origin.x;
+
+        //The window includes the tile itself
+        table.sum(origin, h, w) - (t == kind) as usize
     }
 
     fn update(&mut self) {
+        let tree_table = self.resource_table(Tile::Tree);
+        let lumberyard_table = self.resource_table(Tile::Lumberyard);
+
         for p in self.tiles.coords() {
-            let (trees, lumberyards, _) = self.count_adjacent(p);
-            let t = match self.tiles[p] {
+            let t = self.tiles[p];
+            let trees = Data::count_adjacent(&tree_table, p, t, Tile::Tree);
+            let lumberyards = Data::count_adjacent(&lumberyard_table, p, t, Tile::Lumberyard);
+            let t = match t {
                 Tile::OpenGround if trees >= 3 => Tile::Tree,
                 Tile::OpenGround => Tile::OpenGround,
 
@@ -149,6 +146,17 @@ impl Data {
     }
 }
 
+impl Animate for Data {
+    fn frame(&self) -> Mat2<char> {
+        self.tiles.map(|t| t.to_char())
+    }
+
+    fn step(&mut self) -> bool {
+        self.update();
+        true
+    }
+}
+
 fn part1(d: &Data) -> Result<usize> {
     let mut d = d.clone();
     d.update_mins(10);
@@ -157,24 +165,19 @@ fn part1(d: &Data) -> Result<usize> {
 
 fn part2(d: &Data) -> Result<usize> {
     let min = 1_000_000_000;
-    let mut d = d.clone();
 
-    let v: Vec<usize> = (0..1_000)
-        .map(|_| {
+    let d = fast_forward_by_key(
+        d.clone(),
+        |d| {
+            let mut d = d.clone();
             d.update();
-            d.resource_value()
-        })
-        .collect();
-
-    let last = *v.last().unwrap();
-    let period = v.iter()
-        .enumerate().rev().skip(1)
-        .find(|(_, &n)| n == last)
-        .map(|(i, _)| v.len() - 1 - i )
-        .unwrap();
+            d
+        },
+        |d| d.tiles.clone(),
+        min
+    );
 
-    let ix = v.len() - 1 - ((min - v.len()) % period);
-    Ok(v[ix])
+    Ok(d.resource_value())
 }
 
 impl Solution for Solution18 {
@@ -205,4 +208,8 @@ impl Solution for Solution18 {
         println!("result2: {}", result);
         Ok(())
     }
+
+    fn animate(&mut self) -> Result<()> {
+        Player::default().play(&mut self.data.clone())
+    }
 }
\ No newline at end of file
@@ -2,280 +2,59 @@ use Solution;
 use Result;
 use util::file::data_path;
 use util::file::load;
-use std::str::FromStr;
-use std::collections::BTreeSet;
+use util::diff::report;
+use device::Debugger;
+use device::fast::lockstep;
+use device::Program;
+use device::probe::first_repeat;
+use device::probe::Probe;
+use device::profile::profile;
+use device::Reg;
+use device::symbolic::solve;
+use device::symbolic::Query;
 
 
-#[derive(Default, Clone)]
-pub struct Program {
-    ops: Vec<Opcode>,
-    ip_reg: Reg
-}
-
 #[derive(Default)]
 pub struct Solution21 {
     data: Program,
 }
 
-pub struct Cpu {
-    register: [u64; 6]
-}
-
-#[derive(Copy, Clone, Debug)]
-enum Reg {
-    Reg0,
-    Reg1,
-    Reg2,
-    Reg3,
-    Reg4,
-    Reg5,
-}
-
-#[derive(Debug, Clone)]
-enum Opcode {
-    Addr { a: Reg, b: Reg, c: Reg },
-    Addi { a: Reg, b: u64, c: Reg },
-
-    Mulr { a: Reg, b: Reg, c: Reg },
-    Muli { a: Reg, b: u64, c: Reg },
-
-    Banr { a: Reg, b: Reg, c: Reg },
-    Bani { a: Reg, b: u64, c: Reg },
-
-    Borr { a: Reg, b: Reg, c: Reg },
-    Bori { a: Reg, b: u64, c: Reg },
+///Part 2 runs for minutes, profile a prefix of it
+const PROFILE_LIMIT: u64 = 50_000_000;
 
-    Setr { a: Reg, c: Reg },
-    Seti { a: u64, c: Reg },
+const EQ_RR_IX: usize = 28;
 
-    Gtir { a: u64, b: Reg, c: Reg },
-    Gtri { a: Reg, b: u64, c: Reg },
-    Gtrr { a: Reg, b: Reg, c: Reg },
+///Lockstep runs of the fast interpreter from 24 bit values of register 0,
+///the width of the values it is compared against
+const LOCKSTEP_CASES: usize = 20;
+const LOCKSTEP_SEED: u64 = 21;
+const LOCKSTEP_REG0: u64 = (1 << 24) - 1;
+const LOCKSTEP_CHUNK: u64 = 200_000;
 
-    Eqir { a: u64, b: Reg, c: Reg },
-    Eqri { a: Reg, b: u64, c: Reg },
-    Eqrr { a: Reg, b: Reg, c: Reg },
-}
+///The first halting check happens within a few thousand instructions
+const SYMBOLIC_STEPS: usize = 10_000;
 
-impl Default for Reg {
-    fn default() -> Self {
-        Reg::Reg0
-    }
-}
+///Register 0 that halts after the fewest instructions
+fn part1(p: &Program) -> Result<u64> {
+    let reg0 = Reg::new(0, p.reg_count())?;
+    let query = Query::halt(SYMBOLIC_STEPS).symbolic(reg0);
 
-impl Reg {
-    fn to_index(&self) -> usize {
-        use self::Reg::*;
-        match self {
-            Reg0 => 0,
-            Reg1 => 1,
-            Reg2 => 2,
-            Reg3 => 3,
-            Reg4 => 4,
-            Reg5 => 5
-        }
-    }
-
-    fn from_index(ix: usize) -> Result<Reg> {
-        use self::Reg::*;
-        let reg = match ix {
-            0 => Reg0,
-            1 => Reg1,
-            2 => Reg2,
-            3 => Reg3,
-            4 => Reg4,
-            5 => Reg5,
-            _ => return Err("Invalid reg index".into())
-        };
-
-        Ok(reg)
+    match solve(p, &p.cpu(), &query)? {
+        Some(w) => Ok(w.registers[0]),
+        None => Err("No value of register 0 halts the program".into())
     }
 }
 
-impl Cpu {
-    fn new() -> Cpu {
-        Cpu { register: [0; 6] }
-    }
-
-    fn get_reg(&self, r: Reg) -> u64 {
-        self.register[r.to_index()]
-    }
+///Last new value compared against register 0 before the values repeat
+fn part2(p: &Program) -> Result<u64> {
+    let mut probe = Probe::new(p, p.cpu(), EQ_RR_IX)?;
+    let values = probe.by_ref()
+        .map(|(_, regs)| regs[3]);
 
-    fn set_reg(&mut self, r: Reg, val: u64) {
-        self.register[r.to_index()] = val
-    }
-}
-
-impl Opcode {
-    fn exec(&self, cpu: &mut Cpu) -> Result<()> {
-        use self::Opcode::*;
-        match *self {
-            Addr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a + b);
-            }
-            Addi { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a + b);
-            }
-
-            Mulr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a * b);
-            }
-            Muli { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a * b);
-            }
-
-            Borr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a | b);
-            }
-            Bori { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a | b);
-            }
-
-            Banr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, a & b);
-            }
-            Bani { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a & b);
-            }
-
-            Setr { a, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, a);
-            }
-            Seti { a, c } => {
-                cpu.set_reg(c, a);
-            }
-
-            Gtir { a, b, c } => {
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a > b) as u64);
-            }
-            Gtri { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, (a > b) as u64);
-            }
-            Gtrr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a > b) as u64);
-            }
-
-            Eqir { a, b, c } => {
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a == b) as u64);
-            }
-            Eqri { a, b, c } => {
-                let a = cpu.get_reg(a);
-                cpu.set_reg(c, (a == b) as u64);
-            }
-            Eqrr { a, b, c } => {
-                let a = cpu.get_reg(a);
-                let b = cpu.get_reg(b);
-                cpu.set_reg(c, (a == b) as u64);
-            }
-        }
-
-        Ok(())
-    }
-
-    fn from_instr_str(instr: &str, a: u64, b: u64, c: u64) -> Result<Opcode> {
-        use self::Opcode::*;
-
-        let reg = |i| Reg::from_index(i as usize);
-
-        let op = match instr {
-            "addr" => Addr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
-            "addi" => Addi { a: reg(a)?, b, c: reg(c)? },
-
-            "mulr" => Mulr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
-            "muli" => Muli { a: reg(a)?, b, c: reg(c)? },
-
-            "banr" => Banr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
-            "bani" => Bani { a: reg(a)?, b, c: reg(c)? },
-
-            "borr" => Borr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
-            "bori" => Bori { a: reg(a)?, b, c: reg(c)? },
-
-            "setr" => Setr { a: reg(a)?, c: reg(c)? },
-            "seti" => Seti { a, c: reg(c)? },
-
-            "gtir" => Gtir { a, b: reg(b)?, c: reg(c)? },
-            "gtri" => Gtri { a: reg(a)?, b, c: reg(c)? },
-            "gtrr" => Gtrr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
-
-            "eqir" => Eqir { a, b: reg(b)?, c: reg(c)? },
-            "eqri" => Eqri { a: reg(a)?, b, c: reg(c)? },
-            "eqrr" => Eqrr { a: reg(a)?, b: reg(b)?, c: reg(c)? },
-            _ => return Err("Invalid opcode".into())
-        };
-
-        Ok(op)
-    }
-}
-
-impl Program {
-    fn run_once(&self, cpu: &mut Cpu) -> bool {
-        let ix = cpu.get_reg(self.ip_reg) as usize;
-
-        match self.ops.get(ix) {
-            Some(instr) => {
-                instr.exec(cpu).unwrap();
-
-                let ix = cpu.get_reg(self.ip_reg);
-                cpu.set_reg(self.ip_reg, ix + 1);
-                true
-            },
-            None => false
-        }
-    }
-
-    fn ip(&self, cpu: &Cpu) -> usize {
-        cpu.get_reg(self.ip_reg) as usize
-    }
-}
-
-fn part1(p: &Program) -> u64 {
-    const EQ_RR_IX: usize = 28;
-
-    let mut cpu = Cpu::new();
-
-    while p.ip(&cpu) != EQ_RR_IX {
-        p.run_once(&mut cpu);
-    }
-
-    return cpu.get_reg(Reg::Reg3);
-}
-
-fn part2(p: &Program) -> u64 {
-    let eq_rr_ix = 28;
-    let mut prev = BTreeSet::new();
-    let mut last = 0;
-
-    let mut cpu = Cpu::new();
-
-    loop {
-        if p.ip(&cpu) == eq_rr_ix {
-            let val = cpu.get_reg(Reg::Reg3);
-            if prev.contains(&val) {
-                return last;
-            }
-            last = val;
-            prev.insert(val);
-        }
-
-        p.run_once(&mut cpu);
+    match (first_repeat(values), probe.fault()) {
+        (Some(r), _) => Ok(r.last_new),
+        (None, Some(fault)) => Err(format!("{} at instruction {}", fault, probe.cpu().ip()).into()),
+        (None, None) => Err("The program halted before a value repeated".into())
     }
 }
 
@@ -287,61 +66,33 @@ impl Solution for Solution21 {
     }
 
     fn part1(&mut self) -> Result<()> {
-        let result = part1(&self.data);
+        let result = part1(&self.data)?;
         println!("result1: {}", result);
         Ok(())
     }
 
     fn part2(&mut self) -> Result<()> {
-        let result = part2(&self.data);
+        let result = part2(&self.data)?;
         println!("result2: {}", result);
         Ok(())
     }
-}
-
-impl FromStr for Opcode {
-    type Err = Box<std::error::Error>;
 
-    fn from_str(s: &str) -> Result<Self> {
-        let mut parts = s.split_whitespace();
-        let instr = parts.next().ok_or("no instruction")?;
-        let a = parts.next()
-            .ok_or("no a")?
-            .parse()?;
-        let b = parts.next()
-            .ok_or("no b")?
-            .parse()?;
-        let c = parts.next().ok_or("no c")?
-            .parse()?;
-
-        Opcode::from_instr_str(instr, a, b, c)
+    fn diff(&mut self) -> Result<()> {
+        let m = lockstep(&self.data, LOCKSTEP_CASES, LOCKSTEP_SEED, LOCKSTEP_REG0, LOCKSTEP_CHUNK)?;
+        report("fast interpreter", LOCKSTEP_CASES, m)
     }
-}
 
-impl FromStr for Program {
-    type Err = Box<std::error::Error>;
-
-    fn from_str(s: &str) -> Result<Self> {
-        let mut l = s.lines();
-
-        let ip_line = l.next()
-            .ok_or("no ip line")?;
-        let mut s = ip_line.split_whitespace();
-        s.next().ok_or("no ip first")?;
-
-        let n = s.next()
-            .ok_or("no ip index")?
-            .parse()?;
-
-        let ip_reg = Reg::from_index(n)?;
-
-        let ops = l
-            .map(|l| l.parse())
-            .collect::<Result<Vec<Opcode>>>()?;
+    fn debug(&mut self) -> Result<()> {
+        let stdin = std::io::stdin();
+        let stdout = std::io::stdout();
+        Debugger::new(&self.data, self.data.cpu())
+            .repl(stdin.lock(), stdout.lock())
+    }
 
-        Ok(Program{
-            ip_reg,
-            ops
-        })
+    fn profile(&mut self) -> Result<()> {
+        let mut cpu = self.data.cpu();
+        let prof = profile(&self.data, &mut cpu, Some(PROFILE_LIMIT));
+        print!("{}", prof);
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -3,6 +3,10 @@ use Result;
 use util::file::data_path;
 use util::file::sample_path;
 use util::file::load;
+use util::diff::differential;
+use util::diff::report;
+use util::diff::shrink_i64;
+use util::diff::shrink_vec;
 use std::str::FromStr;
 use std::cmp::Ordering;
 use std::collections::HashSet;
@@ -117,6 +121,46 @@ fn part1(d: &Data) -> Result<usize> {
     Ok(uf.sets())
 }
 
+///Flood fill over all pairs instead of union find
+fn constellations_naive(points: &[Vec4]) -> usize {
+    let mut seen = vec![false; points.len()];
+    let mut count = 0;
+
+    for start in 0..points.len() {
+        if seen[start] {
+            continue;
+        }
+
+        count += 1;
+        seen[start] = true;
+        let mut stack = vec![start];
+        while let Some(i) = stack.pop() {
+            for j in 0..points.len() {
+                if !seen[j] && points[i].dist(&points[j]) <= 3 {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+    }
+
+    count
+}
+
+fn shrink_point(p: &Vec4) -> Vec<Vec4> {
+    let mut result = Vec::new();
+    for w in shrink_i64(p.w) { result.push(Vec4 { w, ..*p }); }
+    for x in shrink_i64(p.x) { result.push(Vec4 { x, ..*p }); }
+    for y in shrink_i64(p.y) { result.push(Vec4 { y, ..*p }); }
+    for z in shrink_i64(p.z) { result.push(Vec4 { z, ..*p }); }
+    result
+}
+
+const DIFF_POINTS: usize = 40;
+const DIFF_COORD: i64 = 6;
+const DIFF_CASES: usize = 500;
+const DIFF_SEED: u64 = 25;
+
 fn part2(_: &Data) -> Result<usize> {
     Ok(0)
 }
@@ -148,6 +192,19 @@ impl Solution for Solution25 {
         println!("result2: {}", result);
         Ok(())
     }
+
+    fn diff(&mut self) -> Result<()> {
+        let m = differential(DIFF_CASES, DIFF_SEED,
+                             |rng| {
+                                 let n = rng.below(DIFF_POINTS + 1);
+                                 let mut c = || rng.range(-DIFF_COORD, DIFF_COORD);
+                                 (0..n).map(|_| Vec4 { w: c(), x: c(), y: c(), z: c() }).collect()
+                             },
+                             |points: &Vec<Vec4>| shrink_vec(points, shrink_point),
+                             |points| Some(constellations_naive(points)),
+                             |points| part1(&Data { points: points.clone() }).ok());
+        report("constellations", DIFF_CASES, m)
+    }
 }
 
 impl FromStr for Vec4 {
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    ///Number of steps before the state enters the cycle
+    pub start: usize,
+    pub period: usize
+}
+
+impl Cycle {
+    ///Index inside the first pass of the cycle that is equivalent to step `n`
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.start {
+            n
+        } else {
+            self.start + (n - self.start) % self.period
+        }
+    }
+}
+
+///State after `n` steps, skipping over whole cycles once the state repeats
+pub fn fast_forward<S, F>(state: S, step: F, n: usize) -> S
+    where S: Clone + Hash + Eq,
+          F: FnMut(&S) -> S {
+    fast_forward_by_key(state, step, |s| s.clone(), n)
+}
+
+///Like `fast_forward`, but states are compared by `key`. Two states with
+///the same key have to behave the same from then on.
+pub fn fast_forward_by_key<S, K, F, FK>(state: S, mut step: F, key: FK, n: usize) -> S
+    where K: Hash + Eq,
+          F: FnMut(&S) -> S,
+          FK: Fn(&S) -> K {
+    let mut seen = HashMap::new();
+    let mut state = state;
+    let mut i = 0;
+
+    while i < n {
+        if let Some(start) = seen.insert(key(&state), i) {
+            //The state at `i` is the one at `start` again
+            let cycle = Cycle {
+                start,
+                period: i - start
+            };
+            for _ in cycle.start..cycle.reduce(n) {
+                state = step(&state);
+            }
+
+            return state;
+        }
+
+        state = step(&state);
+        i += 1;
+    }
+
+    state
+}
+
+///For states that repeat up to a drift, e.g. a pattern that moves along an
+///infinite row. `key` has to ignore the drift, `extrapolate(first, repeat, k)`
+///returns the state `k` periods after `repeat` given its earlier occurrence `first`.
+pub fn fast_forward_with_drift<S, K, F, FK, FE>(state: S, mut step: F, key: FK, mut extrapolate: FE, n: usize) -> S
+    where K: Hash + Eq,
+          F: FnMut(&S) -> S,
+          FK: Fn(&S) -> K,
+          FE: FnMut(&S, &S, usize) -> S {
+    let mut seen: HashMap<K, (usize, S)> = HashMap::new();
+    let mut state = state;
+    let mut i = 0;
+
+    while i < n {
+        let k = key(&state);
+        if let Some(&(start, ref first)) = seen.get(&k) {
+            let cycle = Cycle {
+                start,
+                period: i - start
+            };
+
+            state = extrapolate(first, &state, (n - i) / cycle.period);
+            for _ in cycle.start..cycle.reduce(n) {
+                state = step(&state);
+            }
+
+            return state;
+        }
+
+        let next = step(&state);
+        seen.insert(k, (i, state));
+        state = next;
+        i += 1;
+    }
+
+    state
+}
@@ -0,0 +1,157 @@
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use Result;
+
+///Xorshift generator for test inputs, reproducible from its seed
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+///Input on which a reference and a candidate implementation disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch<I, O> {
+    ///Index of the generated case
+    pub case: usize,
+    pub original: I,
+    ///Smallest disagreeing input found by shrinking `original`
+    pub input: I,
+    pub expected: O,
+    pub actual: O
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    ///Uniform in `lo..=hi`
+    pub fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        let span = (hi - lo) as u64 + 1;
+        lo + (self.next_u64() % span) as i64
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+///Candidates for a smaller number, closest to 0 first
+pub fn shrink_u64(n: u64) -> Vec<u64> {
+    let mut result = Vec::new();
+    let mut d = n;
+    while d > 0 {
+        result.push(n - d);
+        d /= 2;
+    }
+
+    result
+}
+
+pub fn shrink_i64(n: i64) -> Vec<i64> {
+    let mut result: Vec<i64> = shrink_u64(n.unsigned_abs()).into_iter()
+        .map(|m| m as i64 * n.signum())
+        .collect();
+    if n < 0 {
+        result.insert(1, -n);
+    }
+
+    result
+}
+
+///Candidates with chunks of elements removed, then with single elements shrunk
+pub fn shrink_vec<T: Clone, F: Fn(&T) -> Vec<T>>(v: &[T], shrink: F) -> Vec<Vec<T>> {
+    let mut result = Vec::new();
+    let mut chunk = v.len();
+
+    while chunk > 0 {
+        for start in (0..v.len()).step_by(chunk) {
+            let end = (start + chunk).min(v.len());
+            result.push([&v[..start], &v[end..]].concat());
+        }
+        chunk /= 2;
+    }
+
+    for (i, x) in v.iter().enumerate() {
+        for s in shrink(x) {
+            let mut w = v.to_vec();
+            w[i] = s;
+            result.push(w);
+        }
+    }
+
+    result
+}
+
+///Runs `reference` and `candidate` on `cases` generated inputs. The first
+///disagreement is shrunk greedily while the implementations still disagree.
+pub fn differential<I, O, G, S, R, C>(cases: usize, seed: u64, mut generate: G, shrink: S,
+                                      reference: R, candidate: C) -> Option<Mismatch<I, O>>
+    where I: Clone,
+          O: PartialEq,
+          G: FnMut(&mut Rng) -> I,
+          S: Fn(&I) -> Vec<I>,
+          R: Fn(&I) -> O,
+          C: Fn(&I) -> O {
+    let mut rng = Rng::new(seed);
+    let disagree = |input: &I| {
+        let expected = reference(input);
+        let actual = candidate(input);
+        if expected != actual {
+            Some((expected, actual))
+        } else {
+            None
+        }
+    };
+
+    for case in 0..cases {
+        let original = generate(&mut rng);
+        let (mut expected, mut actual) = match disagree(&original) {
+            Some(outputs) => outputs,
+            None => continue
+        };
+
+        let mut input = original.clone();
+        'shrink: loop {
+            for smaller in shrink(&input) {
+                if let Some((e, a)) = disagree(&smaller) {
+                    input = smaller;
+                    expected = e;
+                    actual = a;
+                    continue 'shrink;
+                }
+            }
+            break;
+        }
+
+        return Some(Mismatch { case, original, input, expected, actual });
+    }
+
+    None
+}
+
+///Prints that `name` agreed on all cases, the mismatch is returned as error
+pub fn report<I: Debug, O: Debug>(name: &str, cases: usize, m: Option<Mismatch<I, O>>) -> Result<()> {
+    match m {
+        Some(m) => Err(format!("{}: {}", name, m).into()),
+        None => {
+            println!("{}: agree on {} cases", name, cases);
+            Ok(())
+        }
+    }
+}
+
+impl<I: Debug, O: Debug> Display for Mismatch<I, O> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "case {}: implementations disagree on {:?}", self.case, self.input)?;
+        writeln!(f, "  reference: {:?}", self.expected)?;
+        writeln!(f, "  candidate: {:?}", self.actual)?;
+        write!(f, "  reduced from {:?}", self.original)
+    }
+}
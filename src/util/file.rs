@@ -18,6 +18,11 @@ pub fn sample_path(n: usize) -> String {
     format!("/home/jonas/CLionProjects/aoc/in/{:02}_sample.txt", n)
 }
 
+///Optional JSON configuration of a day, like the units of a day 15 battle
+pub fn scenario_path(n: usize) -> String {
+    format!("/home/jonas/CLionProjects/aoc/in/{:02}_scenario.json", n)
+}
+
 pub fn load(path: &str) -> Result<String> {
     let mut r = File::open(path)?;
     let mut s = String::new();
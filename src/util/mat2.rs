@@ -5,14 +5,14 @@ use std::fmt::Display;
 use std::ops::Index;
 use std::ops::IndexMut;
 
-#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Default, Ord, Eq)]
+#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Default, Ord, Eq, Serialize, Deserialize)]
 pub struct Pos {
     pub x: usize,
     pub y: usize
 }
 
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
 pub struct Mat2<T> {
     h: usize,
     w: usize,
@@ -131,8 +131,204 @@ impl<T> Mat2<T> {
             .map(move |p| self.get(p).unwrap())
     }
 
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.data[y * self.w..(y + 1) * self.w]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item=&[T]> + '_ {
+        (0..self.h)
+            .map(move |y| self.row(y))
+    }
+
+    pub fn col(&self, x: usize) -> impl Iterator<Item=&T> + '_ {
+        assert!(x < self.w, "Mat2 column out of bounds");
+        self.data[x..].iter()
+            .step_by(self.w)
+    }
+
+    pub fn cols(&self) -> impl Iterator<Item=impl Iterator<Item=&T> + '_> + '_ {
+        (0..self.w)
+            .map(move |x| self.col(x))
+    }
+
+    ///Rectangular window of `h` x `w` cells starting at `origin`, clipped to the matrix
+    pub fn view(&self, origin: Pos, h: usize, w: usize) -> View<'_, T> {
+        let h = h.min(self.h.saturating_sub(origin.y));
+        let w = w.min(self.w.saturating_sub(origin.x));
+
+        View {
+            mat: self,
+            origin,
+            h,
+            w
+        }
+    }
+
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> Mat2<U> {
+        Mat2 {
+            h: self.h,
+            w: self.w,
+            data: self.data.iter().map(f).collect()
+        }
+    }
+
+    pub fn zip_with<U, V, F: FnMut(&T, &U) -> V>(&self, other: &Mat2<U>, mut f: F) -> Mat2<V> {
+        assert!(self.h == other.h && self.w == other.w, "Mat2 size");
+
+        Mat2 {
+            h: self.h,
+            w: self.w,
+            data: self.data.iter()
+                .zip(other.data.iter())
+                .map(|(a, b)| f(a, b))
+                .collect()
+        }
+    }
+
+    pub fn count_where<F: FnMut(&T) -> bool>(&self, mut pred: F) -> usize {
+        self.data.iter()
+            .filter(|t| pred(t))
+            .count()
+    }
+}
+
+impl<T: PartialEq> Mat2<T> {
+    pub fn positions_of<'a>(&'a self, val: &'a T) -> impl Iterator<Item=Pos> + 'a {
+        self.coords()
+            .filter(move |&p| self[p] == *val)
+    }
+
+    ///Lists every cell that differs between the two matrices as (pos, old, new)
+    pub fn diff<'a>(&'a self, other: &'a Mat2<T>) -> Vec<(Pos, &'a T, &'a T)> {
+        assert!(self.h == other.h && self.w == other.w, "Mat2 size");
+
+        self.coords()
+            .filter(|&p| self[p] != other[p])
+            .map(|p| (p, &self[p], &other[p]))
+            .collect()
+    }
+}
+
+impl<T: Clone> Mat2<T> {
+    pub fn transpose(&self) -> Mat2<T> {
+        let data = (0..self.w)
+            .flat_map(|x| self.col(x).cloned())
+            .collect();
 
+        Mat2::from_vec(data, self.w, self.h)
+    }
+
+    pub fn flip_horizontal(&self) -> Mat2<T> {
+        let data = self.rows()
+            .flat_map(|r| r.iter().rev().cloned())
+            .collect();
+
+        Mat2::from_vec(data, self.h, self.w)
+    }
+
+    pub fn flip_vertical(&self) -> Mat2<T> {
+        let data = self.rows()
+            .rev()
+            .flat_map(|r| r.iter().cloned())
+            .collect();
+
+        Mat2::from_vec(data, self.h, self.w)
+    }
+
+    pub fn rotate_cw(&self) -> Mat2<T> {
+        self.transpose().flip_horizontal()
+    }
+
+    pub fn rotate_ccw(&self) -> Mat2<T> {
+        self.transpose().flip_vertical()
+    }
 
+    ///Rotates clockwise in steps of 90 degrees
+    pub fn rotate(&self, quarter_turns: usize) -> Mat2<T> {
+        match quarter_turns % 4 {
+            0 => self.clone(),
+            1 => self.rotate_cw(),
+            2 => self.flip_vertical().flip_horizontal(),
+            _ => self.rotate_ccw()
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct View<'a, T: 'a> {
+    mat: &'a Mat2<T>,
+    origin: Pos,
+    h: usize,
+    w: usize
+}
+
+impl<'a, T> View<'a, T> {
+    pub fn height(&self) -> usize {
+        self.h
+    }
+
+    pub fn width(&self) -> usize {
+        self.w
+    }
+
+    pub fn origin(&self) -> Pos {
+        self.origin
+    }
+
+    pub fn get(&self, p: Pos) -> Option<&'a T> {
+        if p.x < self.w && p.y < self.h {
+            self.mat.get(self.origin.add(p))
+        } else {
+            None
+        }
+    }
+
+    pub fn row(&self, y: usize) -> &'a [T] {
+        assert!(y < self.h, "View row out of bounds");
+        let row = self.mat.row(self.origin.y + y);
+        &row[self.origin.x..self.origin.x + self.w]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item=&'a [T]> + '_ {
+        (0..self.h)
+            .map(move |y| self.row(y))
+    }
+
+    ///Positions relative to the view origin
+    pub fn coords(&self) -> impl Iterator<Item=Pos> {
+        let w = self.w;
+        (0..self.h * self.w)
+            .map(move |ix| Pos::new(ix%w, ix/w))
+    }
+
+    pub fn grids(&self) -> impl Iterator<Item=&'a T> + '_ {
+        self.rows()
+            .flat_map(|r| r.iter())
+    }
+
+    pub fn count_where<F: FnMut(&T) -> bool>(&self, mut pred: F) -> usize {
+        self.grids()
+            .filter(|t| pred(t))
+            .count()
+    }
+}
+
+impl<'a, T: Clone> View<'a, T> {
+    pub fn to_mat(&self) -> Mat2<T> {
+        let data = self.grids()
+            .cloned()
+            .collect();
+
+        Mat2::from_vec(data, self.h, self.w)
+    }
+}
+
+impl<'a, T> Index<Pos> for View<'a, T> {
+    type Output = T;
+
+    fn index(&self, index: Pos) -> &T {
+        self.get(index).unwrap()
+    }
 }
 
 impl<T: Default + Clone> Mat2<T> {
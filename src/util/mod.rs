@@ -1,3 +1,8 @@
 pub mod file;
 pub mod nom;
-pub mod mat2;
\ No newline at end of file
+pub mod mat2;
+pub mod player;
+pub mod summed_area;
+pub mod cycle;
+pub mod diff;
+pub mod region;
\ No newline at end of file
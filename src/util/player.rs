@@ -0,0 +1,383 @@
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::StdinLock;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+use util::mat2::Mat2;
+use util::mat2::Pos;
+use Result;
+
+///A simulation that can be rendered frame by frame
+pub trait Animate {
+    fn frame(&self) -> Mat2<char>;
+
+    ///Advances the simulation by one step, returns false once it is finished
+    fn step(&mut self) -> bool;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Action {
+    TogglePause,
+    Step,
+    Faster,
+    Slower,
+    Scroll(isize, isize),
+    Quit,
+}
+
+pub struct Player {
+    delay: Duration,
+    paused: bool,
+    view: Pos,
+    view_h: usize,
+    view_w: usize,
+    steps: usize,
+    finished: bool,
+}
+
+///Switches the terminal into non canonical mode and restores it on drop
+struct RawMode {
+    saved: Option<String>,
+}
+
+///Reads keys on a background thread, stops and joins it on drop
+struct KeyReader {
+    keys: Receiver<Key>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+enum Input {
+    Byte(u8),
+    Timeout,
+    End,
+}
+
+const MIN_DELAY: u64 = 1;
+const MAX_DELAY: u64 = 2_000;
+
+impl Key {
+    fn to_action(self) -> Option<Action> {
+        let cmd = match self {
+            Key::Char(' ') | Key::Char('p') => Action::TogglePause,
+            Key::Char('n') | Key::Char('.') => Action::Step,
+            Key::Char('+') | Key::Char('=') => Action::Faster,
+            Key::Char('-') => Action::Slower,
+            Key::Char('q') | Key::Char('\u{3}') => Action::Quit,
+            Key::Up | Key::Char('w') | Key::Char('k') => Action::Scroll(0, -1),
+            Key::Down | Key::Char('s') | Key::Char('j') => Action::Scroll(0, 1),
+            Key::Left | Key::Char('a') | Key::Char('h') => Action::Scroll(-1, 0),
+            Key::Right | Key::Char('d') | Key::Char('l') => Action::Scroll(1, 0),
+            _ => return None,
+        };
+
+        Some(cmd)
+    }
+}
+
+impl RawMode {
+    fn enable() -> RawMode {
+        let saved = stty(&["-g"])
+            .map(|s| s.trim().to_string());
+
+        // Ctrl-C arrives as a key instead of killing us with the terminal
+        // still raw. Reads return after 0.1s without input.
+        if saved.is_some() {
+            stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "1"]);
+        }
+
+        RawMode { saved }
+    }
+
+    fn is_active(&self) -> bool {
+        self.saved.is_some()
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(ref saved) = self.saved {
+            stty(&[saved]);
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let out = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+
+    if out.status.success() {
+        String::from_utf8(out.stdout).ok()
+    } else {
+        None
+    }
+}
+
+fn terminal_size() -> (usize, usize) {
+    let size = stty(&["size"]).and_then(|s| {
+        let mut it = s.split_whitespace()
+            .map(|n| n.parse::<usize>());
+        match (it.next(), it.next()) {
+            (Some(Ok(h)), Some(Ok(w))) if h > 0 && w > 0 => Some((h, w)),
+            _ => None,
+        }
+    });
+
+    size.unwrap_or((24, 80))
+}
+
+///Next byte of stdin. An empty read is a timeout in raw mode and the end of
+///the input otherwise.
+fn read_byte(stdin: &mut StdinLock, raw: bool) -> Input {
+    let mut b = [0];
+    match stdin.read(&mut b) {
+        Ok(1) => Input::Byte(b[0]),
+        Ok(_) if raw => Input::Timeout,
+        Err(ref e) if e.kind() == ErrorKind::Interrupted => Input::Timeout,
+        _ => Input::End,
+    }
+}
+
+impl KeyReader {
+    fn spawn(raw: &RawMode) -> KeyReader {
+        let (tx, keys) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let raw = raw.is_active();
+
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            let stdin = std::io::stdin();
+            let mut stdin = stdin.lock();
+
+            while !stopped.load(Ordering::Relaxed) {
+                let key = match read_byte(&mut stdin, raw) {
+                    Input::Byte(0x1b) => {
+                        // Arrow keys are sent as ESC [ A..D, a bare ESC times out
+                        let arrow = match read_byte(&mut stdin, raw) {
+                            Input::Byte(b'[') => match read_byte(&mut stdin, raw) {
+                                Input::Byte(b'A') => Some(Key::Up),
+                                Input::Byte(b'B') => Some(Key::Down),
+                                Input::Byte(b'C') => Some(Key::Right),
+                                Input::Byte(b'D') => Some(Key::Left),
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+                        match arrow {
+                            Some(key) => key,
+                            None => continue,
+                        }
+                    }
+                    Input::Byte(b) => Key::Char(b as char),
+                    Input::Timeout => continue,
+                    Input::End => break,
+                };
+
+                if tx.send(key).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Without raw mode a read blocks until input arrives, so the thread
+        // is left to end with the input
+        KeyReader {
+            keys,
+            stop,
+            handle: if raw { Some(handle) } else { None },
+        }
+    }
+}
+
+impl Drop for KeyReader {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        let (h, w) = terminal_size();
+        Player::new(h, w)
+    }
+}
+
+impl Player {
+    pub fn new(term_h: usize, term_w: usize) -> Player {
+        Player {
+            delay: Duration::from_millis(100),
+            paused: true,
+            view: Pos::new(0, 0),
+            // Leave room for the status line
+            view_h: term_h.saturating_sub(2).max(1),
+            view_w: term_w.max(1),
+            steps: 0,
+            finished: false,
+        }
+    }
+
+    pub fn play<A: Animate>(&mut self, sim: &mut A) -> Result<()> {
+        let raw = RawMode::enable();
+        let reader = KeyReader::spawn(&raw);
+        let out = std::io::stdout();
+        let mut out = out.lock();
+
+        // Hide cursor and clear screen
+        write!(out, "\x1b[?25l\x1b[2J")?;
+        let result = self.run(sim, &reader.keys, &mut out);
+        drop(reader);
+        write!(out, "\x1b[0m\x1b[?25h")?;
+        writeln!(out)?;
+        out.flush()?;
+
+        result
+    }
+
+    fn run<A: Animate, W: Write>(&mut self, sim: &mut A, keys: &Receiver<Key>, out: &mut W) -> Result<()> {
+        let mut frame = sim.frame();
+        self.draw(&frame, out)?;
+
+        let mut next_step = Instant::now() + self.delay;
+        loop {
+            let timeout = if self.paused || self.finished {
+                Duration::from_millis(MAX_DELAY)
+            } else {
+                next_step.saturating_duration_since(Instant::now())
+            };
+
+            let mut dirty = false;
+            match keys.recv_timeout(timeout) {
+                Ok(key) => {
+                    match key.to_action() {
+                        Some(Action::Quit) => return Ok(()),
+                        Some(Action::Step) => {
+                            self.paused = true;
+                            self.advance(sim);
+                            frame = sim.frame();
+                        }
+                        Some(cmd) => self.apply(cmd, &frame),
+                        None => {}
+                    }
+                    dirty = true;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                // Stdin is closed, just run the simulation to the end
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    if self.paused || self.finished {
+                        return Ok(());
+                    }
+                    thread::sleep(timeout);
+                }
+            }
+
+            if !self.paused && !self.finished && Instant::now() >= next_step {
+                self.advance(sim);
+                frame = sim.frame();
+                next_step = Instant::now() + self.delay;
+                dirty = true;
+            }
+
+            if dirty {
+                self.draw(&frame, out)?;
+            }
+        }
+    }
+
+    fn advance<A: Animate>(&mut self, sim: &mut A) {
+        if self.finished {
+            return;
+        }
+
+        if sim.step() {
+            self.steps += 1;
+        } else {
+            self.finished = true;
+        }
+    }
+
+    fn apply(&mut self, cmd: Action, frame: &Mat2<char>) {
+        match cmd {
+            Action::TogglePause => self.paused = !self.paused,
+            Action::Faster => {
+                let ms = (self.delay.as_millis() as u64 / 2).max(MIN_DELAY);
+                self.delay = Duration::from_millis(ms);
+            }
+            Action::Slower => {
+                let ms = (self.delay.as_millis() as u64 * 2).min(MAX_DELAY);
+                self.delay = Duration::from_millis(ms);
+            }
+            Action::Scroll(dx, dy) => {
+                let max_x = frame.width().saturating_sub(self.view_w);
+                let max_y = frame.height().saturating_sub(self.view_h);
+                // Scroll by a quarter of the viewport per key press
+                let step_x = (self.view_w / 4).max(1) as isize;
+                let step_y = (self.view_h / 4).max(1) as isize;
+
+                let x = (self.view.x as isize + dx * step_x).max(0) as usize;
+                let y = (self.view.y as isize + dy * step_y).max(0) as usize;
+                self.view = Pos::new(x.min(max_x), y.min(max_y));
+            }
+            Action::Step | Action::Quit => {}
+        }
+    }
+
+    fn draw<W: Write>(&self, frame: &Mat2<char>, out: &mut W) -> Result<()> {
+        let mut s = String::new();
+        s.push_str("\x1b[H");
+
+        let y_end = (self.view.y + self.view_h).min(frame.height());
+        let x_end = (self.view.x + self.view_w).min(frame.width());
+        for y in self.view.y..y_end {
+            for x in self.view.x..x_end {
+                s.push(frame[Pos::new(x, y)]);
+            }
+            s.push_str("\x1b[K\r\n");
+        }
+        s.push_str("\x1b[J");
+
+        let state = match (self.finished, self.paused) {
+            (true, _) => "finished",
+            (false, true) => "paused",
+            (false, false) => "playing",
+        };
+        s.push_str(&format!(
+            "\x1b[7m step {} | {} | {}ms | view {},{} of {}x{} | space: play/pause  n: step  +/-: speed  arrows: scroll  q: quit \x1b[0m",
+            self.steps,
+            state,
+            self.delay.as_millis(),
+            self.view.x,
+            self.view.y,
+            frame.width(),
+            frame.height()
+        ));
+
+        out.write_all(s.as_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+use util::mat2::Mat2;
+use util::mat2::Pos;
+
+impl<T> Mat2<T> {
+    ///Orthogonal neighbors in reading order
+    pub fn neighbors(&self, p: Pos) -> impl Iterator<Item=Pos> + '_ {
+        [(0, -1), (-1, 0), (1, 0), (0, 1)].iter()
+            .filter_map(move |&off| p.offset(off))
+            .filter(move |&p| self.has_pos(p))
+    }
+
+    ///BFS distance from `start` to every reachable cell. The start cell
+    ///is always reached, whether it is passable or not.
+    pub fn flood_fill<F: Fn(&T) -> bool>(&self, start: Pos, passable: F) -> Mat2<Option<usize>> {
+        let mut dist = Mat2::new(self.height(), self.width());
+        if !self.has_pos(start) {
+            return dist;
+        }
+
+        let mut q = VecDeque::new();
+        dist[start] = Some(0);
+        q.push_back(start);
+
+        while let Some(p) = q.pop_front() {
+            let d = dist[p].unwrap();
+            for n in self.neighbors(p) {
+                if dist[n].is_none() && passable(&self[n]) {
+                    dist[n] = Some(d + 1);
+                    q.push_back(n);
+                }
+            }
+        }
+
+        dist
+    }
+}
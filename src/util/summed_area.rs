@@ -0,0 +1,99 @@
+use num_traits::Num;
+use util::mat2::Mat2;
+use util::mat2::Pos;
+
+///Summed-area table for O(1) rectangle sums over a Mat2
+#[derive(Debug, Clone)]
+pub struct SummedAreaTable<T> {
+    //One extra row and column of zeros so lookups need no bound checks
+    table: Mat2<T>,
+}
+
+impl<T: Num + Copy> SummedAreaTable<T> {
+    pub fn new(mat: &Mat2<T>) -> SummedAreaTable<T> {
+        let (h, w) = (mat.height(), mat.width());
+        let mut table = Mat2::new_with(h + 1, w + 1, T::zero());
+
+        for y in 0..h {
+            let mut row_sum = T::zero();
+            for x in 0..w {
+                row_sum = row_sum + mat[Pos::new(x, y)];
+                table[Pos::new(x + 1, y + 1)] = row_sum + table[Pos::new(x + 1, y)];
+            }
+        }
+
+        SummedAreaTable {
+            table
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.table.height() - 1
+    }
+
+    pub fn width(&self) -> usize {
+        self.table.width() - 1
+    }
+
+    ///Sum of the `h` x `w` rectangle at `origin`, clipped to the table
+    pub fn sum(&self, origin: Pos, h: usize, w: usize) -> T {
+        let x0 = origin.x.min(self.width());
+        let y0 = origin.y.min(self.height());
+        let x1 = (origin.x + w).min(self.width());
+        let y1 = (origin.y + h).min(self.height());
+
+        let t = &self.table;
+        t[Pos::new(x1, y1)] + t[Pos::new(x0, y0)]
+            - t[Pos::new(x0, y1)] - t[Pos::new(x1, y0)]
+    }
+
+    pub fn total(&self) -> T {
+        self.sum(Pos::new(0, 0), self.height(), self.width())
+    }
+}
+
+impl<T: Num + Copy + PartialOrd> SummedAreaTable<T> {
+    ///Top left corner and sum of the k x k window with the largest sum,
+    ///ties are broken by reading order
+    pub fn best_window(&self, k: usize) -> Option<(Pos, T)> {
+        if k == 0 || k > self.height() || k > self.width() {
+            return None;
+        }
+
+        let mut best: Option<(Pos, T)> = None;
+        for y in 0..=self.height() - k {
+            for x in 0..=self.width() - k {
+                let p = Pos::new(x, y);
+                let sum = self.sum(p, k, k);
+
+                match best {
+                    Some((_, max)) if max >= sum => {}
+                    _ => best = Some((p, sum))
+                }
+            }
+        }
+
+        best
+    }
+
+    ///Best window for every size k, as (k, pos, sum)
+    pub fn best_windows(&self) -> impl Iterator<Item=(usize, Pos, T)> + '_ {
+        let n = self.height().min(self.width());
+        (1..=n)
+            .filter_map(move |k| self.best_window(k)
+                .map(|(p, sum)| (k, p, sum)))
+    }
+
+    ///Best window over all sizes, smaller windows win ties
+    pub fn best_window_any(&self) -> Option<(usize, Pos, T)> {
+        let mut best: Option<(usize, Pos, T)> = None;
+        for (k, p, sum) in self.best_windows() {
+            match best {
+                Some((_, _, max)) if max >= sum => {}
+                _ => best = Some((k, p, sum))
+            }
+        }
+
+        best
+    }
+}